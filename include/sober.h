@@ -0,0 +1,106 @@
+#ifndef SOBER_H
+#define SOBER_H
+
+/* Generated by cbindgen from src/capi.rs. Don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Why a `SoberResults` has no more solutions.
+typedef enum SoberStatus {
+  // It hasn't stopped yet.
+  SOBER_STATUS_RUNNING,
+  // We enumerated everything up to the maximum level.
+  SOBER_STATUS_EXHAUSTED,
+  SOBER_STATUS_TIMED_OUT,
+  // Something went wrong on our end. The synthesizer won't find anything
+  // more, so it's best freed.
+  SOBER_STATUS_ERROR,
+} SoberStatus;
+
+// The solutions a synthesizer finds, in the order it finds them.
+typedef struct SoberResults SoberResults;
+
+// A synthesizer and its bank.
+typedef struct SoberSynthesizer SoberSynthesizer;
+
+// A task, read from JSON in the task-file format.
+typedef struct SoberTask SoberTask;
+
+
+
+
+
+// Reads a task from `json`, or returns `NULL` if it's not a valid task.
+//
+// # Safety
+//
+// `json` must be a NUL-terminated string, and `error` either `NULL` or
+// writable.
+struct SoberTask *sober_task_from_json(const char *json, char **error);
+
+// # Safety
+//
+// `task` must come from `sober_task_from_json`, or be `NULL`.
+void sober_task_free(struct SoberTask *task);
+
+// Makes a synthesizer for `task` out of the comma-separated components
+// in `vocab`, like the CLI's `--vocab`. `task` stays the caller's.
+//
+// # Safety
+//
+// `task` must be a live task, `vocab` a NUL-terminated string, and
+// `error` either `NULL` or writable.
+struct SoberSynthesizer *sober_synthesizer_new(const struct SoberTask *task,
+                                               const char *vocab,
+                                               char **error);
+
+// # Safety
+//
+// `synth` must come from `sober_synthesizer_new`, or be `NULL`, and
+// have no live results.
+void sober_synthesizer_free(struct SoberSynthesizer *synth);
+
+// Starts looking for solutions up to `max_level`, for at most `timeout`
+// seconds from now. A `timeout` of 0 or less means no timeout.
+//
+// The synthesizer keeps its bank, so later results carry on from where
+// earlier ones stopped, and don't repeat their solutions.
+//
+// # Safety
+//
+// `synth` must be a live synthesizer, and outlive the results, and the
+// results must be freed before asking it for more.
+struct SoberResults *sober_synthesizer_results(struct SoberSynthesizer *synth,
+                                               uint8_t max_level,
+                                               double timeout);
+
+// The code for the next solution, or `NULL` once there are no more;
+// see `sober_results_status` for why. Free it with `sober_string_free`.
+//
+// # Safety
+//
+// `results` must be live.
+char *sober_results_next(struct SoberResults *results);
+
+// # Safety
+//
+// `results` must be live.
+enum SoberStatus sober_results_status(const struct SoberResults *results);
+
+// # Safety
+//
+// `results` must come from `sober_synthesizer_results`, or be `NULL`.
+void sober_results_free(struct SoberResults *results);
+
+// Frees a string we returned, code or error message alike.
+//
+// # Safety
+//
+// `s` must come from us, or be `NULL`.
+void sober_string_free(char *s);
+
+#endif  /* SOBER_H */
@@ -0,0 +1,52 @@
+//! Programs as plain trees, for tools that want to look inside them
+//! without parsing their code.
+
+use serde::Serialize;
+
+use crate::utils::Any;
+use crate::vocab::Op;
+
+/// An owned copy of a program, with its variables and constants by name.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ast {
+    /// A component applied to its arguments, in order.
+    Op {
+        op: Op,
+        args: Vec<Ast>,
+    },
+    Var(String),
+    Literal(Any),
+}
+
+impl Ast {
+    /// The number of nodes in the tree.
+    pub fn size(&self) -> usize {
+        match self {
+            Ast::Op { args, .. } => 1 + args.iter().map(Ast::size).sum::<usize>(),
+            Ast::Var(_) | Ast::Literal(_) => 1,
+        }
+    }
+
+    /// The variables the program refers to, in the order they appear,
+    /// repeated if it refers to them more than once.
+    pub fn vars(&self) -> Vec<&str> {
+        let mut vars = Vec::new();
+        self.visit(&mut |node| {
+            if let Ast::Var(name) = node {
+                vars.push(name.as_str());
+            }
+        });
+        vars
+    }
+
+    /// Calls `f` on every node, parents before their children.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Ast)) {
+        f(self);
+        if let Ast::Op { args, .. } = self {
+            for arg in args {
+                arg.visit(f);
+            }
+        }
+    }
+}
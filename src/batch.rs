@@ -0,0 +1,301 @@
+//! Runs every task file in a directory and summarizes how each one went,
+//! so we can diff the summaries between versions of the synthesizer.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::loader;
+use crate::synth::{self, Limits, Stop, Synthesizer};
+use crate::utils::*;
+use crate::vocab;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum SummaryFormat {
+    Csv,
+    Json,
+}
+
+/// How a single task went.
+#[derive(Serialize, Debug)]
+pub struct Outcome {
+    /// The task's file name.
+    pub task: String,
+    /// `None` if we couldn't even load the task; see `error`.
+    pub status: Option<Stop>,
+    /// How long the search took. That's until we finished the solution's
+    /// level, to rank it against the rest, or until we gave up.
+    pub search_seconds: f64,
+    /// The last level we enumerated.
+    pub level: u8,
+    pub ints: usize,
+    pub strs: usize,
+    pub int_arrays: usize,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    fn failed(task: String, error: String) -> Self {
+        Self {
+            task,
+            status: None,
+            search_seconds: 0.0,
+            level: 0,
+            ints: 0,
+            strs: 0,
+            int_arrays: 0,
+            code: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// The `.json` files in `dir`, sorted so summaries are stable.
+pub fn task_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Runs each of `files` on up to `jobs` threads, looking for one solution.
+/// `timeout` applies to each task separately.
+/// The outcomes are in the same order as `files`.
+pub fn run(
+    files: &[PathBuf],
+    vocab: &[String],
+    max_level: u8,
+    timeout: Option<Duration>,
+    jobs: usize,
+) -> Vec<Outcome> {
+    // Tasks and vocabs aren't `Send`, so each worker loads its own.
+    let next = Mutex::new(files.iter().enumerate());
+    let outcomes = Mutex::new(Vec::with_capacity(files.len()));
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| loop {
+                let Some((i, file)) = next.lock().unwrap().next() else {
+                    break;
+                };
+                let outcome = run_one(file, vocab, max_level, timeout);
+                outcomes.lock().unwrap().push((i, outcome));
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(i, _)| *i);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+fn run_one(file: &Path, vocab: &[String], max_level: u8, timeout: Option<Duration>) -> Outcome {
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let task = match loader::load_task(file) {
+        Ok(task) => task,
+        Err(e) => return Outcome::failed(name, e.to_string()),
+    };
+    let vocab = match vocab::select(vocab) {
+        Ok(vocab) => vocab,
+        Err(e) => return Outcome::failed(name, e),
+    };
+
+    let start = Instant::now();
+    let mut limits = Limits::new(max_level);
+    if let Some(timeout) = timeout {
+        limits = limits.with_timeout(timeout);
+    }
+
+    let mut synth = Synthesizer::new(vocab, task);
+    let (solutions, stop) = synth.solutions(1, &limits, &synth::default_ranking);
+    let search_seconds = start.elapsed().as_secs_f64();
+    let store = synth.store();
+
+    Outcome {
+        task: name,
+        status: Some(stop),
+        search_seconds,
+        level: synth.level().min(max_level),
+        ints: store.count(Type::Int),
+        strs: store.count(Type::Str),
+        int_arrays: store.count(Type::IntArray),
+        code: solutions.first().map(|prog| prog.code(store)),
+        error: None,
+    }
+}
+
+pub fn write_summary(
+    out: &mut dyn Write,
+    format: SummaryFormat,
+    outcomes: &[Outcome],
+) -> std::io::Result<()> {
+    match format {
+        SummaryFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, outcomes)?;
+            writeln!(out)?;
+        }
+        SummaryFormat::Csv => {
+            writeln!(
+                out,
+                "task,status,search_seconds,level,ints,strs,int_arrays,code,error"
+            )?;
+            for outcome in outcomes {
+                let status = match outcome.status {
+                    Some(Stop::Found) => "found",
+                    Some(Stop::Exhausted) => "exhausted",
+                    Some(Stop::TimedOut) => "timed_out",
+                    Some(Stop::BankFull) => "bank_full",
+                    Some(Stop::Cancelled) => "cancelled",
+                    None => "error",
+                };
+                writeln!(
+                    out,
+                    "{},{status},{:.6},{},{},{},{},{},{}",
+                    csv_field(&outcome.task),
+                    outcome.search_seconds,
+                    outcome.level,
+                    outcome.ints,
+                    outcome.strs,
+                    outcome.int_arrays,
+                    csv_field(outcome.code.as_deref().unwrap_or_default()),
+                    csv_field(outcome.error.as_deref().unwrap_or_default()),
+                )?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+/// Quotes `field` if it needs it. Code often has commas and quotes in it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(task: &str, code: Option<&str>, error: Option<&str>) -> Outcome {
+        Outcome {
+            task: task.to_string(),
+            status: Some(Stop::Found),
+            search_seconds: 0.5,
+            level: 2,
+            ints: 3,
+            strs: 4,
+            int_arrays: 5,
+            code: code.map(str::to_string),
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn runs_each_task_in_order() {
+        let dir = std::env::temp_dir().join(format!("sober-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tasks = [
+            (
+                "a.json",
+                r#"{"examples":2,"variables":{"x":[1,2]},"output":[2,3]}"#,
+            ),
+            (
+                "b.json",
+                r#"{"examples":2,"variables":{"x":[1]},"output":[2,3]}"#,
+            ),
+            (
+                "c.json",
+                r#"{"examples":1,"variables":{"x":[1]},"output":["no"]}"#,
+            ),
+            ("notes.txt", "not a task"),
+        ];
+        for (name, json) in tasks {
+            std::fs::write(dir.join(name), json).unwrap();
+        }
+
+        let files = task_files(&dir).unwrap();
+        let outcomes = run(&files, &["all".to_string()], 2, None, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let summary: Vec<_> = outcomes
+            .iter()
+            .map(|outcome| {
+                (
+                    outcome.task.as_str(),
+                    outcome.status,
+                    outcome.code.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("a.json", Some(Stop::Found), Some("x + 1")),
+                ("b.json", None, None),
+                ("c.json", Some(Stop::Exhausted), None),
+            ]
+        );
+        assert!(outcomes[1].error.is_some());
+        assert_eq!(outcomes[2].level, 2);
+    }
+
+    #[test]
+    fn quotes_csv_fields_that_need_it() {
+        let outcomes = [
+            outcome("a.json", Some("x + 1"), None),
+            outcome("b, c.json", Some("s + \"a,b\""), Some("line one\nline two")),
+        ];
+        let mut out = Vec::new();
+        write_summary(&mut out, SummaryFormat::Csv, &outcomes).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "task,status,search_seconds,level,ints,strs,int_arrays,code,error\n\
+             a.json,found,0.500000,2,3,4,5,x + 1,\n\
+             \"b, c.json\",found,0.500000,2,3,4,5,\"s + \"\"a,b\"\"\",\"line one\nline two\"\n"
+        );
+    }
+
+    #[test]
+    fn writes_json_summaries() {
+        let mut out = Vec::new();
+        write_summary(
+            &mut out,
+            SummaryFormat::Json,
+            &[outcome("a.json", Some("x + 1"), None)],
+        )
+        .unwrap();
+        let summary: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            summary,
+            serde_json::json!([{
+                "task": "a.json",
+                "status": "found",
+                "search_seconds": 0.5,
+                "level": 2,
+                "ints": 3,
+                "strs": 4,
+                "int_arrays": 5,
+                "code": "x + 1",
+                "error": null,
+            }])
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! A C API, for editors that can't link Rust directly. The header is
+//! `include/sober.h`, generated with `cbindgen --config cbindgen.toml -o include/sober.h`.
+//! The tests check that it's up to date, and that C code links against it.
+//!
+//! ```c
+//! char *error = NULL;
+//! SoberTask *task = sober_task_from_json(json, &error);
+//! SoberSynthesizer *synth = sober_synthesizer_new(task, "all", &error);
+//! SoberResults *results = sober_synthesizer_results(synth, 4, 1.0);
+//! for (char *code; (code = sober_results_next(results)); sober_string_free(code))
+//!     puts(code);
+//! sober_results_free(results);
+//! sober_synthesizer_free(synth);
+//! sober_task_free(task);
+//! ```
+//!
+//! Everything we hand out is owned by the caller, and has a matching
+//! `_free` function. Functions that can fail return `NULL`, and point
+//! `error` at a message, unless `error` is `NULL` itself.
+//!
+//! Panics never unwind into C. They're caught and reported like any other
+//! error, or as `SOBER_STATUS_ERROR` while synthesizing, after which the
+//! synthesizer won't find anything more.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::loader;
+use crate::synth::{Limits, Stop, Synthesizer};
+use crate::utils::*;
+use crate::vocab;
+
+/// A task, read from JSON in the task-file format.
+pub struct SoberTask {
+    /// Kept as JSON, since every synthesizer needs its own copy.
+    json: Value,
+}
+
+/// A synthesizer and its bank.
+pub struct SoberSynthesizer {
+    synth: Synthesizer,
+    /// Whether we panicked while synthesizing, leaving the bank in who knows
+    /// what state.
+    failed: bool,
+}
+
+/// The solutions a synthesizer finds, in the order it finds them.
+pub struct SoberResults {
+    synth: *mut SoberSynthesizer,
+    limits: Limits,
+    status: SoberStatus,
+}
+
+/// Why a `SoberResults` has no more solutions.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoberStatus {
+    /// It hasn't stopped yet.
+    Running,
+    /// We enumerated everything up to the maximum level.
+    Exhausted,
+    TimedOut,
+    /// Something went wrong on our end. The synthesizer won't find anything
+    /// more, so it's best freed.
+    Error,
+}
+
+/// Points `error` at `message`, if the caller wants to know.
+unsafe fn set_error(error: *mut *mut c_char, message: impl ToString) {
+    if !error.is_null() {
+        *error = into_c_string(message.to_string());
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Code and messages never have NULs in them, but better safe than sorry.
+    CString::new(s.replace('\0', "\\0"))
+        .expect("NULs were escaped")
+        .into_raw()
+}
+
+/// Runs `f`, turning a panic into an error, since unwinding into C is
+/// undefined behavior.
+fn catch<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(format!("internal error: {message}").into())
+    })
+}
+
+unsafe fn read_str<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err("unexpected NULL string".into());
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// Reads a task from `json`, or returns `NULL` if it's not a valid task.
+///
+/// # Safety
+///
+/// `json` must be a NUL-terminated string, and `error` either `NULL` or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn sober_task_from_json(
+    json: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut SoberTask {
+    let task = catch(|| {
+        let json: Value = serde_json::from_str(read_str(json)?)?;
+        // Catch mistakes here, rather than when we synthesize.
+        loader::task_from_value(json.clone())?;
+        Ok(SoberTask { json })
+    });
+
+    match task {
+        Ok(task) => Box::into_raw(Box::new(task)),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `task` must come from `sober_task_from_json`, or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn sober_task_free(task: *mut SoberTask) {
+    if !task.is_null() {
+        let _ = catch(|| {
+            drop(Box::from_raw(task));
+            Ok(())
+        });
+    }
+}
+
+/// Makes a synthesizer for `task` out of the comma-separated components
+/// in `vocab`, like the CLI's `--vocab`. `task` stays the caller's.
+///
+/// # Safety
+///
+/// `task` must be a live task, `vocab` a NUL-terminated string, and
+/// `error` either `NULL` or writable.
+#[no_mangle]
+pub unsafe extern "C" fn sober_synthesizer_new(
+    task: *const SoberTask,
+    vocab: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut SoberSynthesizer {
+    let synth = catch(|| {
+        let task = task.as_ref().ok_or("unexpected NULL task")?;
+        let names: Vec<String> = read_str(vocab)?
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect();
+        let vocab = vocab::select(&names)?;
+        let task = loader::task_from_value(task.json.clone())?;
+        Ok(SoberSynthesizer {
+            synth: Synthesizer::new(vocab, task),
+            failed: false,
+        })
+    });
+
+    match synth {
+        Ok(synth) => Box::into_raw(Box::new(synth)),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `synth` must come from `sober_synthesizer_new`, or be `NULL`, and
+/// have no live results.
+#[no_mangle]
+pub unsafe extern "C" fn sober_synthesizer_free(synth: *mut SoberSynthesizer) {
+    if !synth.is_null() {
+        let _ = catch(|| {
+            drop(Box::from_raw(synth));
+            Ok(())
+        });
+    }
+}
+
+/// Starts looking for solutions up to `max_level`, for at most `timeout`
+/// seconds from now. A `timeout` of 0 or less means no timeout.
+///
+/// The synthesizer keeps its bank, so later results carry on from where
+/// earlier ones stopped, and don't repeat their solutions.
+///
+/// # Safety
+///
+/// `synth` must be a live synthesizer, and outlive the results, and the
+/// results must be freed before asking it for more.
+#[no_mangle]
+pub unsafe extern "C" fn sober_synthesizer_results(
+    synth: *mut SoberSynthesizer,
+    max_level: u8,
+    timeout: f64,
+) -> *mut SoberResults {
+    let Some(failed) = synth.as_ref().map(|synth| synth.failed) else {
+        return ptr::null_mut();
+    };
+
+    let results = catch(|| {
+        let mut limits = Limits::new(max_level.max(1));
+        if let Ok(timeout) = Duration::try_from_secs_f64(timeout) {
+            if !timeout.is_zero() {
+                limits = limits.with_timeout(timeout);
+            }
+        }
+
+        Ok(SoberResults {
+            synth,
+            limits,
+            status: if failed {
+                SoberStatus::Error
+            } else {
+                SoberStatus::Running
+            },
+        })
+    });
+
+    match results {
+        Ok(results) => Box::into_raw(Box::new(results)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// The code for the next solution, or `NULL` once there are no more;
+/// see `sober_results_status` for why. Free it with `sober_string_free`.
+///
+/// # Safety
+///
+/// `results` must be live.
+#[no_mangle]
+pub unsafe extern "C" fn sober_results_next(results: *mut SoberResults) -> *mut c_char {
+    let Some(results) = results.as_mut() else {
+        return ptr::null_mut();
+    };
+    if results.status != SoberStatus::Running {
+        return ptr::null_mut();
+    }
+
+    let synth = &mut *results.synth;
+    let next = catch(|| {
+        let mut solutions = synth.synth.results(&results.limits);
+        Ok(match solutions.next() {
+            Some(snippet) => Ok(synth.synth.get(snippet).code()),
+            None => Err(solutions.stop()),
+        })
+    });
+
+    match next {
+        Ok(Ok(code)) => return into_c_string(code),
+        Ok(Err(stop)) => results.status = status(stop),
+        Err(_) => {
+            synth.failed = true;
+            results.status = SoberStatus::Error;
+        }
+    }
+    ptr::null_mut()
+}
+
+fn status(stop: Option<Stop>) -> SoberStatus {
+    match stop {
+        None => SoberStatus::Running,
+        Some(Stop::TimedOut) => SoberStatus::TimedOut,
+        // We never cancel, and running out of solutions isn't a reason to stop.
+        Some(Stop::Exhausted | Stop::BankFull | Stop::Cancelled | Stop::Found) => {
+            SoberStatus::Exhausted
+        }
+    }
+}
+
+/// # Safety
+///
+/// `results` must be live.
+#[no_mangle]
+pub unsafe extern "C" fn sober_results_status(results: *const SoberResults) -> SoberStatus {
+    results
+        .as_ref()
+        .map_or(SoberStatus::Running, |results| results.status)
+}
+
+/// # Safety
+///
+/// `results` must come from `sober_synthesizer_results`, or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn sober_results_free(results: *mut SoberResults) {
+    if !results.is_null() {
+        let _ = catch(|| {
+            drop(Box::from_raw(results));
+            Ok(())
+        });
+    }
+}
+
+/// Frees a string we returned, code or error message alike.
+///
+/// # Safety
+///
+/// `s` must come from us, or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn sober_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        let _ = catch(|| {
+            drop(CString::from_raw(s));
+            Ok(())
+        });
+    }
+}
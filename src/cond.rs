@@ -19,6 +19,10 @@ impl Mutation {
         Self { pointer, values }
     }
 
+    pub fn pointer(&self) -> Pointer {
+        self.pointer
+    }
+
     /// This function "applies" the mutation.
     /// It simply means adding the mutation as a new variable to the store,
     /// and returning a new condition containing the mutated value for the variable.
@@ -74,6 +78,19 @@ impl Condition {
         }
     }
 
+    /// The value this condition requires of `var`, if any.
+    pub fn get(&self, var: Pointer) -> Option<AnyVal> {
+        self.inner[var]
+    }
+
+    /// The variables this condition constrains, with their values.
+    pub fn constrained(&self) -> impl Iterator<Item = (Pointer, AnyVal)> + '_ {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(var, val)| val.map(|val| (var, val)))
+    }
+
     pub fn implies(&self, other: &Condition) -> bool {
         debug_assert_eq!(self.inner.len(), other.inner.len());
         for i in 0..self.inner.len() {
@@ -0,0 +1,233 @@
+//! A long-running server on a Unix domain socket, which keeps each task's
+//! bank around so that later requests don't start over from level 1.
+//!
+//! Clients send one JSON request per line, and get one JSON response per
+//! line back:
+//!
+//! ```json
+//! { "task": { "examples": 1, "variables": { "x": [1] }, "output": [2] },
+//!   "max_level": 4, "timeout": 1.5, "solutions": 3, "lang": "python" }
+//! ```
+//!
+//! `task` is anything the `loader` reads, and the rest is optional.
+//! `lang` picks the language of the solutions' code, see `Lang`.
+//! Requests whose tasks have the same variables, examples, constants and
+//! constraints share a session, even if they ask for different outputs.
+//! A deeper request picks up where the last one stopped.
+//!
+//! Sessions aren't `Send`, so requests are handled one at a time on the
+//! daemon's thread. Each connection is read on a thread of its own, though,
+//! so a client that stays connected doesn't hold up the others.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::lang::Lang;
+use crate::loader;
+use crate::output;
+use crate::synth::{Limits, Synthesizer};
+use crate::utils::*;
+use crate::vocab;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    task: Value,
+    #[serde(default)]
+    max_level: Option<u8>,
+    #[serde(default)]
+    timeout: Option<f64>,
+    #[serde(default)]
+    solutions: Option<usize>,
+    #[serde(default)]
+    lang: Lang,
+}
+
+/// The defaults for requests that don't say otherwise.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub vocab: Vec<String>,
+    pub max_level: u8,
+    pub timeout: Option<Duration>,
+    /// How many banks we keep around, dropping the least recently used.
+    pub max_sessions: usize,
+}
+
+struct Session {
+    synth: Synthesizer,
+    last_used: Instant,
+}
+
+pub struct Daemon {
+    config: Config,
+    sessions: HashMap<String, Session>,
+}
+
+impl Daemon {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Listens on `path` until we're killed.
+    pub fn serve(&mut self, path: &Path) -> Result<(), Error> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(format!("{} exists and isn't a socket", path.display()).into());
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!("{} is already in use", path.display()).into());
+            }
+            // Left behind by a daemon that didn't get to clean up.
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let (requests, incoming) = mpsc::channel::<(String, Sender<Value>)>();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let requests = requests.clone();
+                let client = stream.map(|stream| {
+                    std::thread::spawn(move || {
+                        // One client hanging up badly shouldn't take everyone else down.
+                        if let Err(e) = read_client(stream, requests) {
+                            eprintln!("error: {e}");
+                        }
+                    })
+                });
+                if let Err(e) = client {
+                    eprintln!("error: {e}");
+                }
+            }
+        });
+
+        for (line, reply) in incoming {
+            let response = match self.handle(&line) {
+                Ok(response) => response,
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            // The client may have hung up in the meantime.
+            let _ = reply.send(response);
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, line: &str) -> Result<Value, Error> {
+        let request: Request = serde_json::from_str(line)?;
+        let key = session_key(&request.task);
+        let task = loader::task_from_value(request.task)?;
+
+        let mut limits = Limits::new(request.max_level.unwrap_or(self.config.max_level).max(1));
+        let timeout = match request.timeout {
+            Some(secs) => Some(Duration::try_from_secs_f64(secs)?),
+            None => self.config.timeout,
+        };
+        if let Some(timeout) = timeout {
+            limits = limits.with_timeout(timeout);
+        }
+
+        let resumed = match self.sessions.get_mut(&key) {
+            Some(session) => match session.synth.retarget(task) {
+                Ok(()) => true,
+                Err(task) => {
+                    session.synth = Synthesizer::new(vocab::select(&self.config.vocab)?, *task);
+                    false
+                }
+            },
+            None => {
+                self.evict();
+                let synth = Synthesizer::new(vocab::select(&self.config.vocab)?, task);
+                self.sessions.insert(
+                    key.clone(),
+                    Session {
+                        synth,
+                        last_used: Instant::now(),
+                    },
+                );
+                false
+            }
+        };
+
+        let session = self
+            .sessions
+            .get_mut(&key)
+            .expect("we just made sure it's there");
+        session.last_used = Instant::now();
+        let synth = &mut session.synth;
+
+        let (solutions, stop) = synth.best(request.solutions.unwrap_or(1), &limits);
+
+        let mut response = output::report(synth, &solutions, stop, request.lang);
+        response["resumed"] = resumed.into();
+        response["level"] = synth.level().min(limits.max_level()).into();
+        response["bank"] = json!({
+            "int": synth.bank_size(Type::Int),
+            "str": synth.bank_size(Type::Str),
+            "int_array": synth.bank_size(Type::IntArray),
+        });
+        Ok(response)
+    }
+
+    /// Makes room for one more session.
+    fn evict(&mut self) {
+        while self.sessions.len() >= self.config.max_sessions.max(1) {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(key, _)| key.clone())
+                .expect("there's at least one session");
+            self.sessions.remove(&oldest);
+        }
+    }
+}
+
+/// Passes each request from `stream` on to the daemon's thread,
+/// and writes back the responses in order.
+fn read_client(
+    stream: UnixStream,
+    requests: Sender<(String, Sender<Value>)>,
+) -> std::io::Result<()> {
+    let mut out = stream.try_clone()?;
+    let (reply, responses) = mpsc::channel();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if requests.send((line, reply.clone())).is_err() {
+            // The daemon is shutting down.
+            break;
+        }
+        let response = responses.recv().expect("the daemon answers every request");
+        writeln!(out, "{response}")?;
+    }
+
+    Ok(())
+}
+
+/// Identifies the tasks that would build the same bank.
+/// Object keys are sorted, so equal inputs give equal keys.
+fn session_key(task: &Value) -> String {
+    json!({
+        "examples": task["examples"],
+        "variables": task["variables"],
+        "constants": task["constants"],
+        "read_only": task["read_only"],
+        "must_use": task["must_use"],
+        "must_not_use": task["must_not_use"],
+    })
+    .to_string()
+}
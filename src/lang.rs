@@ -0,0 +1,427 @@
+//! Prints programs in languages other than the JavaScript we synthesize.
+//!
+//! Each language is a `Backend`, which says how to write each component.
+//! Components without an equivalent in a language are `Unsupported` there.
+//! Some only have one when their value isn't used, like `x++` in Python,
+//! which becomes `x += 1` as a whole statement.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::Ast;
+use crate::utils::Any;
+use crate::vocab::{ConstVal, Op, ATOM};
+
+/// How a component is written in some language.
+#[derive(Clone, Copy)]
+pub struct Syntax {
+    /// How tightly the code holds together, higher binding tighter.
+    /// Backends use JavaScript's scale, see `Op::precedence`.
+    pub precedence: u8,
+    /// The lowest precedence each argument can have without parentheses.
+    pub arg_precedence: [u8; 2],
+    /// Puts together the code for the arguments.
+    pub template: fn(&[String]) -> String,
+}
+
+impl Syntax {
+    fn new(precedence: u8, arg_precedence: [u8; 2], template: fn(&[String]) -> String) -> Self {
+        Self {
+            precedence,
+            arg_precedence,
+            template,
+        }
+    }
+
+    /// Written like the JavaScript, with only the template changed.
+    fn like_js(op: Op, template: fn(&[String]) -> String) -> Self {
+        Self::new(
+            op.precedence(),
+            [op.arg_precedence(0), op.arg_precedence(1)],
+            template,
+        )
+    }
+}
+
+pub trait Backend {
+    fn lang(&self) -> Lang;
+
+    /// How `op` is written, or `None` if the language has no equivalent.
+    /// `statement` is whether the code stands on its own, so that its
+    /// value isn't used.
+    fn syntax(&self, op: Op, statement: bool) -> Option<Syntax>;
+
+    fn literal(&self, value: &Any) -> String;
+}
+
+/// The languages we can print programs in.
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Lang {
+    #[default]
+    Js,
+    Python,
+    Rust,
+}
+
+impl Lang {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lang::Js => "JavaScript",
+            Lang::Python => "Python",
+            Lang::Rust => "Rust",
+        }
+    }
+
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Lang::Js => &JavaScript,
+            Lang::Python => &Python,
+            Lang::Rust => &Rust,
+        }
+    }
+
+    /// Prints `ast` in this language, as an expression whose value is used.
+    pub fn render(&self, ast: &Ast) -> Result<String, Unsupported> {
+        render(ast, self.backend(), false)
+    }
+
+    /// Like `render`, but as a whole statement, whose value is thrown away.
+    pub fn render_statement(&self, ast: &Ast) -> Result<String, Unsupported> {
+        render(ast, self.backend(), true)
+    }
+}
+
+/// A component a language has no equivalent for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Unsupported {
+    pub op: Op,
+    pub lang: Lang,
+    /// Whether it would have been supported as a whole statement.
+    pub only_as_statement: bool,
+}
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` has no {} equivalent",
+            self.op.name(),
+            self.lang.name()
+        )?;
+        if self.only_as_statement {
+            write!(f, " unless it's a whole statement")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Prints `ast` with `backend`, with only the parentheses it needs.
+/// `statement` is whether it stands on its own, see `Backend::syntax`.
+pub fn render(ast: &Ast, backend: &dyn Backend, statement: bool) -> Result<String, Unsupported> {
+    render_node(ast, backend, statement).map(|(code, _)| code)
+}
+
+/// The code for `ast` and its precedence.
+fn render_node(
+    ast: &Ast,
+    backend: &dyn Backend,
+    statement: bool,
+) -> Result<(String, u8), Unsupported> {
+    match ast {
+        Ast::Var(name) => Ok((name.clone(), ATOM)),
+        Ast::Literal(value) => {
+            let precedence = match value {
+                // `-1` is the minus of `1`, as far as parsing goes.
+                Any::Int(value) if *value < 0 => Op::Minus.precedence(),
+                _ => ATOM,
+            };
+            Ok((backend.literal(value), precedence))
+        }
+        Ast::Op { op, args } => {
+            let syntax = backend.syntax(*op, statement).ok_or(Unsupported {
+                op: *op,
+                lang: backend.lang(),
+                only_as_statement: !statement && backend.syntax(*op, true).is_some(),
+            })?;
+
+            let args: Vec<String> = args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let (code, precedence) = render_node(arg, backend, false)?;
+                    // `-` in front of `-x` would make `--x`.
+                    let merges = *op == Op::Minus && code.starts_with('-');
+                    Ok(if precedence < syntax.arg_precedence[i] || merges {
+                        format!("({code})")
+                    } else {
+                        code
+                    })
+                })
+                .try_collect()?;
+
+            Ok(((syntax.template)(&args), syntax.precedence))
+        }
+    }
+}
+
+/// The code we synthesize, so it prints just like `Program::code`.
+pub struct JavaScript;
+
+impl Backend for JavaScript {
+    fn lang(&self) -> Lang {
+        Lang::Js
+    }
+
+    fn syntax(&self, op: Op, _: bool) -> Option<Syntax> {
+        let template: fn(&[String]) -> String = match op {
+            Op::Sum => |args| format!("{} + {}", args[0], args[1]),
+            Op::Sub => |args| format!("{} - {}", args[0], args[1]),
+            Op::Minus => |args| format!("-{}", args[0]),
+            Op::Inc => |args| format!("{}++", args[0]),
+            Op::StrLen | Op::ArrLen => |args| format!("{}.length", args[0]),
+            Op::StrDeref | Op::ArrDeref => |args| format!("{}[{}]", args[0], args[1]),
+            Op::ArrSlice => |args| format!("{}.slice({})", args[0], args[1]),
+            Op::ArrPush => |args| format!("{}.push({})", args[0], args[1]),
+        };
+        Some(Syntax::like_js(op, template))
+    }
+
+    fn literal(&self, value: &Any) -> String {
+        ConstVal::from(value.clone()).code().to_string()
+    }
+}
+
+pub struct Python;
+
+impl Backend for Python {
+    fn lang(&self) -> Lang {
+        Lang::Python
+    }
+
+    fn syntax(&self, op: Op, statement: bool) -> Option<Syntax> {
+        Some(match op {
+            Op::Sum | Op::Sub | Op::Minus | Op::StrDeref | Op::ArrDeref => {
+                JavaScript.syntax(op, statement)?
+            }
+            Op::StrLen | Op::ArrLen => {
+                Syntax::new(op.precedence(), [0, 0], |args| format!("len({})", args[0]))
+            }
+            Op::ArrSlice => Syntax::like_js(op, |args| format!("{}[{}:]", args[0], args[1])),
+            // These return `None` in Python, rather than the length or old value.
+            Op::ArrPush if statement => {
+                Syntax::like_js(op, |args| format!("{}.append({})", args[0], args[1]))
+            }
+            Op::Inc if statement => Syntax::new(0, [ATOM, 0], |args| format!("{} += 1", args[0])),
+            Op::ArrPush | Op::Inc => return None,
+        })
+    }
+
+    fn literal(&self, value: &Any) -> String {
+        match value {
+            Any::Int(value) => value.to_string(),
+            // JSON string literals are valid Python too.
+            Any::Str(value) => {
+                serde_json::to_string(value).expect("Strings are always valid JSON.")
+            }
+            Any::IntArray(values) => format!("{values:?}"),
+        }
+    }
+}
+
+/// Ints are `i32`s, strings `&str`s and arrays `Vec<i32>`s.
+pub struct Rust;
+
+/// The precedence of `as`, between unary operators and `*`.
+const RUST_AS: u8 = 13;
+
+impl Backend for Rust {
+    fn lang(&self) -> Lang {
+        Lang::Rust
+    }
+
+    fn syntax(&self, op: Op, statement: bool) -> Option<Syntax> {
+        let member = Op::StrLen.precedence();
+        let unary = Op::Minus.precedence();
+        Some(match op {
+            Op::Sum | Op::Sub | Op::Minus => JavaScript.syntax(op, statement)?,
+            Op::StrLen | Op::ArrLen => Syntax::new(RUST_AS, [member, 0], |args| {
+                format!("{}.len() as i32", args[0])
+            }),
+            // Only ASCII strings are indexed, so bytes are characters.
+            Op::StrDeref => Syntax::new(member, [member, unary], |args| {
+                format!("{}[{} as usize..][..1]", args[0], args[1])
+            }),
+            Op::ArrDeref => Syntax::new(member, [member, unary], |args| {
+                format!("{}[{} as usize]", args[0], args[1])
+            }),
+            // These return `()` in Rust, rather than the length or old value.
+            Op::ArrPush if statement => JavaScript.syntax(op, statement)?,
+            Op::Inc if statement => Syntax::new(0, [ATOM, 0], |args| format!("{} += 1", args[0])),
+            // Slicing panics where JavaScript counts from the end or clamps.
+            Op::ArrSlice | Op::ArrPush | Op::Inc => return None,
+        })
+    }
+
+    fn literal(&self, value: &Any) -> String {
+        match value {
+            Any::Int(value) => value.to_string(),
+            // `Debug` escapes strings the way Rust string literals do.
+            Any::Str(value) => format!("{value:?}"),
+            Any::IntArray(values) => format!("vec!{values:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader;
+    use crate::parse::parse;
+    use crate::synth::Synthesizer;
+    use crate::utils::Type;
+    use crate::vocab::Vocab;
+
+    fn ast(code: &str) -> Ast {
+        parse(code, &|name| match name {
+            "x" | "y" => Some(Type::Int),
+            "s" => Some(Type::Str),
+            "arr" => Some(Type::IntArray),
+            _ => None,
+        })
+        .expect("valid code")
+    }
+
+    fn render(lang: Lang, code: &str) -> Result<String, Unsupported> {
+        lang.render(&ast(code))
+    }
+
+    #[test]
+    fn renders_javascript_as_parsed() {
+        for code in [
+            "x - (y + 1)",
+            "-(-1)",
+            "s[arr.length - 1]",
+            "arr.slice(x)",
+            "x++",
+        ] {
+            assert_eq!(render(Lang::Js, code).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn renders_python() {
+        assert_eq!(
+            render(Lang::Python, "s.length + arr[0]").unwrap(),
+            "len(s) + arr[0]"
+        );
+        assert_eq!(render(Lang::Python, "arr.slice(-x)").unwrap(), "arr[-x:]");
+        assert_eq!(
+            render(Lang::Python, "-arr.slice(1).length").unwrap(),
+            "-len(arr[1:])"
+        );
+        assert_eq!(render(Lang::Python, "-(x - y)").unwrap(), "-(x - y)");
+        assert_eq!(render(Lang::Python, r#""a\"b""#).unwrap(), r#""a\"b""#);
+        assert_eq!(
+            Lang::Python.render_statement(&ast("x++")).unwrap(),
+            "x += 1"
+        );
+        assert_eq!(
+            Lang::Python.render_statement(&ast("arr.push(y)")).unwrap(),
+            "arr.append(y)"
+        );
+    }
+
+    #[test]
+    fn renders_rust() {
+        assert_eq!(
+            render(Lang::Rust, "-s.length").unwrap(),
+            "-(s.len() as i32)"
+        );
+        assert_eq!(
+            render(Lang::Rust, "arr[x - 1]").unwrap(),
+            "arr[(x - 1) as usize]"
+        );
+        assert_eq!(render(Lang::Rust, "s[0]").unwrap(), "s[0 as usize..][..1]");
+        assert_eq!(render(Lang::Rust, "[1, 2]").unwrap(), "vec![1, 2]");
+        assert_eq!(
+            render(Lang::Rust, "x - (s.length + arr.length)").unwrap(),
+            "x - (s.len() as i32 + arr.len() as i32)"
+        );
+        assert_eq!(
+            render(Lang::Rust, "arr[-(-1)]").unwrap(),
+            "arr[-(-1) as usize]"
+        );
+        assert_eq!(
+            Lang::Rust.render_statement(&ast("arr.push(x)")).unwrap(),
+            "arr.push(x)"
+        );
+        assert_eq!(Lang::Rust.render_statement(&ast("x++")).unwrap(), "x += 1");
+    }
+
+    #[test]
+    fn reports_unsupported_components() {
+        let err = render(Lang::Rust, "arr.slice(1)").unwrap_err();
+        assert_eq!(
+            (err.op, err.lang, err.only_as_statement),
+            (Op::ArrSlice, Lang::Rust, false)
+        );
+
+        // Used as a value, even at the top.
+        let err = render(Lang::Python, "x++").unwrap_err();
+        assert_eq!((err.op, err.only_as_statement), (Op::Inc, true));
+        assert_eq!(
+            err.to_string(),
+            "`inc` has no Python equivalent unless it's a whole statement"
+        );
+
+        let err = render(Lang::Python, "arr.push(1) + 1").unwrap_err();
+        assert_eq!((err.op, err.only_as_statement), (Op::ArrPush, true));
+
+        // Only the top of a statement throws its value away.
+        let err = Lang::Rust
+            .render_statement(&ast("arr.push(x++)"))
+            .unwrap_err();
+        assert_eq!((err.op, err.only_as_statement), (Op::Inc, true));
+    }
+
+    #[test]
+    fn exports_the_trees_programs_are_parsed_from() {
+        let task = loader::parse_task(
+            r#"{"examples":2,"variables":{"x":[1,2],"y":[3,4],"s":["ab","cd"],"arr":[[1,2],[3]]}}"#,
+        )
+        .unwrap();
+        let mut synth = Synthesizer::new(Vocab::all(), task);
+
+        let mut ops = Vec::new();
+        for code in [
+            "x + 1",
+            "x - y",
+            "-x",
+            "s.length",
+            "s[0]",
+            "arr.length",
+            "arr[0]",
+            "arr.slice(1)",
+            "x++",
+            "arr.push(y)",
+            "s[arr.length - 1]",
+            "-arr.slice(x).length",
+            r#""ab"[[1, 2].length - 1]"#,
+        ] {
+            let expected = ast(code);
+            let snippet = synth.parse(code).unwrap();
+            assert_eq!(synth.get(snippet).to_ast(), expected, "{code}");
+
+            expected.visit(&mut |node| {
+                if let Ast::Op { op, .. } = node {
+                    ops.push(*op);
+                }
+            });
+        }
+        assert!(Op::ALL.iter().all(|op| ops.contains(op)));
+    }
+}
@@ -0,0 +1,129 @@
+//! Bottom-up enumerative synthesis of JavaScript snippets from examples.
+//!
+//! Describe what the snippet should do with a `SynthesisTask`, either built
+//! in code or read by the `loader`, pick the components to build it from
+//! with a `Vocab`, and hand both to a `Synthesizer`:
+//!
+//! ```
+//! use sober::{Anies, Limits, SynthesisTask, Synthesizer, Vocab};
+//!
+//! let task = SynthesisTask::new([("x".to_string(), Anies::Int(vec![1, 2]))].into(), 2)
+//!     .with_output(Anies::Int(vec![2, 3]))
+//!     .with_after_state("x".to_string(), Anies::Int(vec![1, 2]));
+//! let mut synth = Synthesizer::new(Vocab::all(), task);
+//!
+//! let (found, _stop) = synth.best(1, &Limits::new(3));
+//! assert_eq!(synth.get(found[0]).code(), "x + 1");
+//! ```
+//!
+//! `best_by` ranks solutions some other way than `default_ranking`,
+//! and predicates describe solutions we can't give the output of:
+//!
+//! ```
+//! use sober::{default_ranking, loader, Anies, Int, Limits, SnippetRef, Synthesizer, Vocab};
+//!
+//! let task = loader::parse_task(r#"{"examples":2,"variables":{"x":[1,2]}}"#)?
+//!     .with_predicate(|values: &[Int], after| {
+//!         let Anies::Int(x) = &after["x"] else { return false };
+//!         values.iter().zip(x).all(|(value, x)| value > x)
+//!     });
+//! let mut synth = Synthesizer::new(Vocab::all(), task);
+//!
+//! // The longest code first.
+//! let longest = |lhs: &SnippetRef, rhs: &SnippetRef| {
+//!     rhs.code().len().cmp(&lhs.code().len()).then_with(|| default_ranking(lhs, rhs))
+//! };
+//! let (found, _stop) = synth.best_by(3, &Limits::new(2), &longest);
+//! assert!(found.iter().all(|snippet| synth.get(*snippet).is_solution()));
+//! # Ok::<(), sober::Error>(())
+//! ```
+//!
+//! The synthesizer is also an `Iterator` over every program it enumerates,
+//! which stops at the `Limits` given to `with_limits`, or once it runs out:
+//!
+//! ```
+//! # use sober::{loader, Limits, Snippet, Synthesizer, Vocab};
+//! # let task = loader::parse_task(r#"{"examples":1,"variables":{"x":[1]},"output":[2]}"#)?;
+//! let mut synth = Synthesizer::new(Vocab::all(), task).with_limits(Limits::new(3));
+//! let programs: Vec<Snippet> = synth.by_ref().collect();
+//! println!("stopped because {:?}", synth.stop());
+//! # Ok::<(), sober::Error>(())
+//! ```
+//!
+//! Programs live in the synthesizer's bank, so they're handed out as
+//! `Snippet`s, and looked at through the `Synthesizer` they came from.
+//! Code written elsewhere can join them with `Synthesizer::parse`, which
+//! evaluates it on the examples:
+//!
+//! ```
+//! # use sober::{loader, Anies, Synthesizer, Vocab};
+//! # let task = loader::parse_task(
+//! #     r#"{"examples":2,"variables":{"x":[1,2],"s":["abc","de"]},"output":["b","d"]}"#,
+//! # )?;
+//! # let mut synth = Synthesizer::new(Vocab::all(), task);
+//! let snippet = synth.parse("s[s.length - x]")?;
+//! assert_eq!(
+//!     synth.get(snippet).values(),
+//!     Anies::Str(vec!["c".to_string(), "d".to_string()])
+//! );
+//! # Ok::<(), sober::Error>(())
+//! ```
+//!
+//! Sketches leave `??` holes in the code for the bank to fill:
+//!
+//! ```
+//! # use sober::{loader, Limits, Synthesizer, Vocab};
+//! # let task = loader::parse_task(
+//! #     r#"{"examples":2,"variables":{"x":[1,2],"s":["abc","de"]},"output":["c","e"]}"#,
+//! # )?;
+//! # let mut synth = Synthesizer::new(Vocab::all(), task);
+//! let sketch = synth.sketch("s[?? - 1]")?;
+//! for filling in synth.fill_sketch(sketch, &Limits::new(3)) {
+//!     println!("{}", filling.code);
+//! }
+//! # Ok::<(), sober::Error>(())
+//! ```
+
+#![allow(dead_code)]
+#![feature(try_trait_v2)]
+#![feature(iterator_try_collect)]
+#![feature(ascii_char)]
+
+mod ast;
+mod capi;
+mod cond;
+mod ops;
+mod parse;
+mod sketch;
+mod store;
+mod synth;
+mod task;
+mod utils;
+mod vocab;
+
+pub mod batch;
+pub mod daemon;
+pub mod lang;
+pub mod loader;
+pub mod lsp;
+pub mod output;
+pub mod repl;
+
+#[cfg(feature = "python")]
+mod python;
+
+pub(crate) use cond::*;
+pub(crate) use ops::*;
+pub(crate) use store::*;
+pub(crate) use utils::*;
+
+pub use ast::Ast;
+pub use lang::{Backend, Lang, Unsupported};
+pub use parse::ParseError;
+pub use sketch::{Filling, Fillings, Sketch};
+pub use synth::{
+    default_ranking, Enumeration, Limits, Ranking, Snippet, SnippetRef, Stop, Synthesizer,
+};
+pub use task::{AfterState, AnyPredicate, Component, Mismatch, Predicate, SynthesisTask};
+pub use utils::{Anies, Any, Error, Int, IntArray, MaybeAnies, Str, Type};
+pub use vocab::{constants, Assoc, ConstVal, Op, Vocab, ATOM};
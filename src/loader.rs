@@ -0,0 +1,444 @@
+//! Loads synthesis tasks from JSON files.
+//!
+//! A task file looks like this:
+//!
+//! ```json
+//! {
+//!     "examples": 2,
+//!     "variables": {
+//!         "x": [0, 2],
+//!         "s": ["a", "asdfmovie"],
+//!         "arr": { "type": "IntArray", "values": [[], [1, 2]] }
+//!     },
+//!     "output": [1, null],
+//!     "after": { "x": [1, 3] },
+//!     "constants": [0, 1, "", []],
+//!     "read_only": ["s"],
+//!     "must_use": [{ "var": "x" }],
+//!     "must_not_use": [{ "op": "arr_slice" }, { "const": "1" }],
+//!     "forbidden_outputs": [{ "example": 0, "value": 3 }],
+//!     "forbidden_after": [{ "example": 1, "var": "x", "value": 2 }]
+//! }
+//! ```
+//!
+//! Only `examples` and `variables` are required. Values are either a plain
+//! list, whose type is inferred (see `Anies`), or a `{ "type", "values" }`
+//! object. `null` in `output` or `after` means we don't care about that
+//! example. `after` states and forbidden after states must have the type of
+//! their variable. Leaving out `constants` uses the defaults from the vocab.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::store::MAX_CONSTRAINTS;
+use crate::task::{Component, SynthesisTask};
+use crate::utils::*;
+use crate::vocab::ConstVal;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file is valid JSON, but not a valid task.
+    Invalid(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "Could not read task file: {err}"),
+            LoadError::Json(err) => write!(f, "Malformed task file: {err}"),
+            LoadError::Invalid(msg) => write!(f, "Invalid task: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Json(err) => Some(err),
+            LoadError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(value: std::io::Error) -> Self {
+        LoadError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(value: serde_json::Error) -> Self {
+        LoadError::Json(value)
+    }
+}
+
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        LoadError::Invalid(format!($($arg)*))
+    };
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskFile {
+    examples: usize,
+    variables: HashMap<String, ValuesFile>,
+    #[serde(default)]
+    output: Option<ValuesFile>,
+    #[serde(default)]
+    after: HashMap<String, ValuesFile>,
+    #[serde(default)]
+    constants: Option<Vec<Value>>,
+    #[serde(default)]
+    read_only: Vec<String>,
+    #[serde(default)]
+    must_use: Vec<Component>,
+    #[serde(default)]
+    must_not_use: Vec<Component>,
+    #[serde(default)]
+    forbidden_outputs: Vec<ForbiddenOutput>,
+    #[serde(default)]
+    forbidden_after: Vec<ForbiddenAfter>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ValuesFile {
+    Typed {
+        #[serde(rename = "type")]
+        ty: Type,
+        values: Value,
+    },
+    Inferred(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ForbiddenOutput {
+    example: usize,
+    value: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ForbiddenAfter {
+    example: usize,
+    var: String,
+    value: Value,
+}
+
+/// Reads and parses the task file at `path`.
+pub fn load_task(path: impl AsRef<Path>) -> Result<SynthesisTask, LoadError> {
+    let json = std::fs::read_to_string(path)?;
+    parse_task(&json)
+}
+
+/// Parses a task from its JSON representation.
+pub fn parse_task(json: &str) -> Result<SynthesisTask, LoadError> {
+    task_from_file(serde_json::from_str(json)?)
+}
+
+/// Builds a task from an already parsed JSON value.
+pub fn task_from_value(value: Value) -> Result<SynthesisTask, LoadError> {
+    task_from_file(serde_json::from_value(value)?)
+}
+
+fn task_from_file(file: TaskFile) -> Result<SynthesisTask, LoadError> {
+    let examples = file.examples;
+    if examples == 0 {
+        return Err(invalid!("a task needs at least one example"));
+    }
+
+    let mut before_state = HashMap::new();
+    for (name, values) in file.variables {
+        let values: Anies = convert(values, &format!("variable `{name}`"))?;
+        check_len(values.len(), examples, &format!("variable `{name}`"))?;
+        before_state.insert(name, values);
+    }
+
+    let mut task = SynthesisTask::new(before_state, examples);
+
+    if let Some(output) = file.output {
+        let output: MaybeAnies = convert(output, "output")?;
+        check_len(output.len(), examples, "output")?;
+        task = task.with_output(output);
+    }
+
+    for (name, values) in file.after {
+        let what = format!("after state of `{name}`");
+        check_var(&task, &name, &what)?;
+        let values: MaybeAnies = convert_as(values, task.before_state()[&name].ty(), &what)?;
+        check_len(values.len(), examples, &what)?;
+        task = task.with_after_state(name, values);
+    }
+
+    if let Some(constants) = file.constants {
+        let constants: Vec<ConstVal> = constants
+            .into_iter()
+            .map(|value| Ok(any(value, "constant")?.into()))
+            .collect::<Result<_, LoadError>>()?;
+
+        for (i, con) in constants.iter().enumerate() {
+            if constants[..i]
+                .iter()
+                .any(|other| other.code() == con.code())
+            {
+                return Err(invalid!("constant {} is listed twice", con.code()));
+            }
+        }
+
+        task = task.with_constants(constants);
+    }
+
+    for name in file.read_only {
+        check_var(&task, &name, "read-only variable")?;
+        task = task.with_read_only(name);
+    }
+
+    for component in file.must_use {
+        check_component(&task, &component)?;
+        task = task.with_must_use(component);
+    }
+
+    for component in file.must_not_use {
+        check_component(&task, &component)?;
+        task = task.with_must_not_use(component);
+    }
+
+    for forbidden in file.forbidden_outputs {
+        check_example(forbidden.example, examples)?;
+        let value = any(forbidden.value, "forbidden output")?;
+        task = task.with_forbidden_output(forbidden.example, value);
+    }
+
+    for forbidden in file.forbidden_after {
+        check_example(forbidden.example, examples)?;
+        check_var(&task, &forbidden.var, "forbidden after state")?;
+        let value = any(forbidden.value, "forbidden after state")?;
+        let ty = task.before_state()[&forbidden.var].ty();
+        if value.ty() != ty {
+            return Err(invalid!(
+                "forbidden after state: must be of type {ty:?}, not {:?}",
+                value.ty()
+            ));
+        }
+        task = task.with_forbidden_after_state(forbidden.example, forbidden.var, value);
+    }
+
+    Ok(task)
+}
+
+/// Values that can be deserialized with a given type,
+/// rather than inferring it from their untagged variants.
+trait Typed: DeserializeOwned {
+    fn typed(ty: Type, values: Value) -> serde_json::Result<Self>;
+}
+
+impl Typed for Anies {
+    fn typed(ty: Type, values: Value) -> serde_json::Result<Self> {
+        Ok(match ty {
+            Type::Int => Anies::Int(serde_json::from_value(values)?),
+            Type::Str => Anies::Str(serde_json::from_value(values)?),
+            Type::IntArray => Anies::IntArray(serde_json::from_value(values)?),
+        })
+    }
+}
+
+impl Typed for MaybeAnies {
+    fn typed(ty: Type, values: Value) -> serde_json::Result<Self> {
+        Ok(match ty {
+            Type::Int => MaybeAnies::Int(serde_json::from_value(values)?),
+            Type::Str => MaybeAnies::Str(serde_json::from_value(values)?),
+            Type::IntArray => MaybeAnies::IntArray(serde_json::from_value(values)?),
+        })
+    }
+}
+
+fn convert<T: Typed>(values: ValuesFile, what: &str) -> Result<T, LoadError> {
+    match values {
+        ValuesFile::Typed { ty, values } => {
+            T::typed(ty, values).map_err(|_| invalid!("{what}: values are not all of type {ty:?}"))
+        }
+        ValuesFile::Inferred(values) => serde_json::from_value(values)
+            .map_err(|_| invalid!("{what}: values must all be Int, Str, or IntArray")),
+    }
+}
+
+/// Like `convert`, but the values must have type `ty`, which is also
+/// used instead of inference for untyped values, so `[null, null]` works
+/// for any type.
+fn convert_as<T: Typed>(values: ValuesFile, ty: Type, what: &str) -> Result<T, LoadError> {
+    let values = match values {
+        ValuesFile::Typed { ty: given, .. } if given != ty => {
+            return Err(invalid!("{what}: must be of type {ty:?}, not {given:?}"));
+        }
+        ValuesFile::Typed { values, .. } | ValuesFile::Inferred(values) => values,
+    };
+    T::typed(ty, values).map_err(|_| invalid!("{what}: values are not all of type {ty:?}"))
+}
+
+fn any(value: Value, what: &str) -> Result<Any, LoadError> {
+    serde_json::from_value(value).map_err(|_| invalid!("{what}: must be Int, Str, or IntArray"))
+}
+
+fn check_len(len: usize, examples: usize, what: &str) -> Result<(), LoadError> {
+    if len == examples {
+        Ok(())
+    } else {
+        Err(invalid!("{what}: expected {examples} values, found {len}"))
+    }
+}
+
+fn check_example(example: usize, examples: usize) -> Result<(), LoadError> {
+    if example < examples {
+        Ok(())
+    } else {
+        Err(invalid!("example {example} is out of range"))
+    }
+}
+
+fn check_var(task: &SynthesisTask, name: &str, what: &str) -> Result<(), LoadError> {
+    if task.var_map.iter().any(|var| var == name) {
+        Ok(())
+    } else {
+        Err(invalid!("{what}: unknown variable `{name}`"))
+    }
+}
+
+fn check_component(task: &SynthesisTask, component: &Component) -> Result<(), LoadError> {
+    if task.must_use().len() + task.must_not_use().len() >= MAX_CONSTRAINTS {
+        return Err(invalid!(
+            "at most {MAX_CONSTRAINTS} components can be in `must_use` and `must_not_use`"
+        ));
+    }
+
+    match component {
+        Component::Var(name) => check_var(task, name, "component"),
+        Component::Const(code) if !task.constants().iter().any(|c| c.code() == code) => {
+            Err(invalid!("component: unknown constant {code}"))
+        }
+        Component::Const(_) | Component::Op(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(json: &str) -> String {
+        match parse_task(json) {
+            Err(LoadError::Invalid(msg)) => msg,
+            Err(err) => panic!("expected an invalid task, got {err}"),
+            Ok(_) => panic!("expected an invalid task, but it loaded"),
+        }
+    }
+
+    #[test]
+    fn loads_a_full_task() {
+        let task = parse_task(
+            r#"{
+                "examples": 2,
+                "variables": { "x": [0, 2], "s": ["a", "bc"] },
+                "output": [1, null],
+                "after": { "x": [1, 3], "s": [null, null] },
+                "constants": [0, 1, ""],
+                "read_only": ["s"],
+                "must_use": [{ "var": "x" }],
+                "must_not_use": [{ "const": "1" }],
+                "forbidden_after": [{ "example": 1, "var": "s", "value": "" }]
+            }"#,
+        )
+        .expect("valid task");
+        assert_eq!(task.examples(), 2);
+        assert_eq!(task.constants().len(), 3);
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(matches!(parse_task("{"), Err(LoadError::Json(_))));
+        assert!(matches!(
+            parse_task(r#"{ "examples": 1, "variables": {}, "outputs": [1] }"#),
+            Err(LoadError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_inconsistent_tasks() {
+        assert_eq!(
+            invalid(r#"{ "examples": 0, "variables": {} }"#),
+            "a task needs at least one example"
+        );
+        assert_eq!(
+            invalid(r#"{ "examples": 2, "variables": { "x": [1] } }"#),
+            "variable `x`: expected 2 values, found 1"
+        );
+        assert_eq!(
+            invalid(r#"{ "examples": 1, "variables": { "x": [1] }, "read_only": ["y"] }"#),
+            "read-only variable: unknown variable `y`"
+        );
+        assert_eq!(
+            invalid(r#"{ "examples": 1, "variables": { "x": [1] }, "constants": [1, 1] }"#),
+            "constant 1 is listed twice"
+        );
+        assert_eq!(
+            invalid(
+                r#"{ "examples": 1, "variables": { "x": [1] },
+                     "forbidden_outputs": [{ "example": 1, "value": 0 }] }"#
+            ),
+            "example 1 is out of range"
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_component_constraints() {
+        let vars = (0..40)
+            .map(|i| format!(r#""x{i}": [{i}]"#))
+            .collect::<Vec<_>>();
+        let uses = (0..40)
+            .map(|i| format!(r#"{{ "var": "x{i}" }}"#))
+            .collect::<Vec<_>>();
+        let task = |must_not_use: usize| {
+            format!(
+                r#"{{ "examples": 1, "variables": {{ {} }}, "must_use": [{}], "must_not_use": [{}] }}"#,
+                vars.join(", "),
+                uses.join(", "),
+                uses[..must_not_use].join(", ")
+            )
+        };
+
+        assert!(parse_task(&task(24)).is_ok());
+        assert_eq!(
+            invalid(&task(25)),
+            "at most 64 components can be in `must_use` and `must_not_use`"
+        );
+    }
+
+    #[test]
+    fn rejects_after_states_of_the_wrong_type() {
+        assert_eq!(
+            invalid(r#"{ "examples": 1, "variables": { "x": [1] }, "after": { "x": [[1]] } }"#),
+            "after state of `x`: values are not all of type Int"
+        );
+        assert_eq!(
+            invalid(
+                r#"{ "examples": 1, "variables": { "x": [1] },
+                     "after": { "x": { "type": "Str", "values": ["a"] } } }"#
+            ),
+            "after state of `x`: must be of type Int, not Str"
+        );
+        assert_eq!(
+            invalid(
+                r#"{ "examples": 1, "variables": { "x": [1] },
+                     "forbidden_after": [{ "example": 0, "var": "x", "value": "a" }] }"#
+            ),
+            "forbidden after state: must be of type Int, not Str"
+        );
+    }
+}
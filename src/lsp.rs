@@ -0,0 +1,539 @@
+//! A Language Server Protocol server over stdio, for synthesizing snippets
+//! from inside the editor.
+//!
+//! Besides the standard lifecycle messages, the client tells us about
+//! examples with a `sober/setExamples` notification:
+//!
+//! ```json
+//! { "textDocument": { "uri": "file:///a.js" }, "position": { "line": 3, "character": 0 },
+//!   "task": { "examples": 1, "variables": { "x": [1] }, "after": { "x": [2] } } }
+//! ```
+//!
+//! where `task` is anything the `loader` reads. A `textDocument/completion`
+//! or `textDocument/codeAction` request on that line then runs the
+//! synthesizer on a background thread, and answers with the best candidates.
+//! Either request may also carry its own `task` instead.
+//!
+//! Requests are cancelled by `$/cancelRequest`, and by any change to
+//! their document, since the user has kept typing.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::loader;
+use crate::synth::{self, Limits, Stop, Synthesizer};
+use crate::utils::*;
+use crate::vocab;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// How hard to look for candidates.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub vocab: Vec<String>,
+    pub max_level: u8,
+    pub timeout: Option<Duration>,
+    pub solutions: usize,
+}
+
+impl Config {
+    /// Overrides the settings the client sends as `initializationOptions`:
+    /// `vocab`, `maxLevel`, `timeout` (in seconds) and `solutions`.
+    fn update(&mut self, options: &Value) -> Result<(), Error> {
+        if let Some(vocab) = options.get("vocab") {
+            let vocab: Vec<String> = serde_json::from_value(vocab.clone())?;
+            vocab::select(&vocab)?;
+            self.vocab = vocab;
+        }
+        if let Some(level) = options.get("maxLevel").and_then(Value::as_u64) {
+            self.max_level = level.clamp(1, 255) as u8;
+        }
+        if let Some(secs) = options.get("timeout").and_then(Value::as_f64) {
+            self.timeout = Some(Duration::try_from_secs_f64(secs)?);
+        }
+        if let Some(solutions) = options.get("solutions").and_then(Value::as_u64) {
+            self.solutions = solutions as usize;
+        }
+        Ok(())
+    }
+}
+
+/// What to answer with once the candidates are in.
+enum Reply {
+    Completion,
+    /// Code actions replace `range` with the candidate.
+    CodeAction {
+        uri: String,
+        range: Value,
+    },
+}
+
+/// A request we're still synthesizing for.
+struct Job {
+    uri: String,
+    cancel: Arc<AtomicBool>,
+}
+
+struct Server {
+    config: Config,
+    /// The task for each line we were given examples for, by document.
+    examples: HashMap<String, HashMap<u64, Value>>,
+    /// Running jobs, by request id. Jobs remove themselves when done.
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    out: Sender<Value>,
+}
+
+/// Serves the client on `input` and `output` until it tells us to exit.
+pub fn serve(
+    config: Config,
+    input: &mut dyn BufRead,
+    output: impl Write + Send + 'static,
+) -> Result<(), Error> {
+    let (out, messages) = mpsc::channel::<Value>();
+
+    // Both the main loop and the jobs answer the client, so a single thread does the writing.
+    let writer = std::thread::spawn(move || -> std::io::Result<()> {
+        let mut output = output;
+        for message in messages {
+            let body = message.to_string();
+            write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+            output.flush()?;
+        }
+        Ok(())
+    });
+
+    let mut server = Server {
+        config,
+        examples: HashMap::new(),
+        jobs: Arc::new(Mutex::new(HashMap::new())),
+        out,
+    };
+
+    while let Some(body) = read_message(input)? {
+        let message = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                // We can't tell which request it was, so the reply has no id.
+                server.send(error(Value::Null, PARSE_ERROR, e.to_string()));
+                continue;
+            }
+        };
+        if !server.handle(message)? {
+            break;
+        }
+    }
+
+    server.cancel_all();
+    drop(server);
+    writer.join().expect("the writer thread panicked")?;
+    Ok(())
+}
+
+/// Reads the body of one message, or `None` once the client hangs up.
+fn read_message(input: &mut dyn BufRead) -> Result<Option<Vec<u8>>, Error> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let mut body = vec![0; len.ok_or("message without a Content-Length")?];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+impl Server {
+    /// Handles one message. Returns `false` once we should exit.
+    fn handle(&mut self, message: Value) -> Result<bool, Error> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            ("exit", _) => return Ok(false),
+            ("initialize", Some(id)) => {
+                let reply = match self.config.update(&params["initializationOptions"]) {
+                    Ok(()) => response(
+                        id,
+                        json!({
+                            "capabilities": {
+                                "textDocumentSync": 1,
+                                "completionProvider": {},
+                                "codeActionProvider": true,
+                            },
+                            "serverInfo": { "name": "sober" },
+                        }),
+                    ),
+                    Err(e) => error(id, INVALID_PARAMS, e.to_string()),
+                };
+                self.send(reply);
+            }
+            ("shutdown", Some(id)) => {
+                self.cancel_all();
+                self.send(response(id, Value::Null));
+            }
+            ("sober/setExamples", _) => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let line = params["position"]["line"].as_u64().unwrap_or_default();
+                let task = params["task"].clone();
+
+                match loader::task_from_value(task.clone()) {
+                    Ok(_) => {
+                        self.examples
+                            .entry(uri.to_string())
+                            .or_default()
+                            .insert(line, task);
+                    }
+                    Err(e) => self.send(json!({
+                        "jsonrpc": "2.0",
+                        "method": "window/showMessage",
+                        "params": { "type": 1, "message": format!("sober: {e}") },
+                    })),
+                }
+            }
+            ("textDocument/didChange", _) => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.cancel_document(uri);
+            }
+            ("textDocument/didClose", _) => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.cancel_document(uri);
+                self.examples.remove(uri);
+            }
+            ("$/cancelRequest", _) => {
+                if let Some(job) = self.jobs.lock().unwrap().get(&params["id"].to_string()) {
+                    job.cancel.store(true, Ordering::Relaxed);
+                }
+            }
+            ("textDocument/completion", Some(id)) => {
+                let line = &params["position"]["line"];
+                self.start(id, params, line, Reply::Completion);
+            }
+            ("textDocument/codeAction", Some(id)) => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let reply = Reply::CodeAction {
+                    uri: uri.to_string(),
+                    range: params["range"].clone(),
+                };
+                self.start(id, params, &params["range"]["start"]["line"], reply);
+            }
+            (method, Some(id)) => {
+                self.send(error(
+                    id,
+                    METHOD_NOT_FOUND,
+                    format!("unknown method `{method}`"),
+                ));
+            }
+            // Notifications we don't care about, like `initialized` or `didOpen`.
+            (_, None) => (),
+        }
+
+        Ok(true)
+    }
+
+    fn send(&self, message: Value) {
+        // This only fails if the writer is gone, and then there's no one to tell.
+        let _ = self.out.send(message);
+    }
+
+    /// Starts synthesizing for a request, with the task it carries,
+    /// or the examples for its line.
+    fn start(&mut self, id: Value, params: &Value, line: &Value, reply: Reply) {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let task = match &params["task"] {
+            Value::Null => line
+                .as_u64()
+                .and_then(|line| self.examples.get(uri)?.get(&line))
+                .cloned(),
+            task => Some(task.clone()),
+        };
+
+        let Some(task) = task else {
+            // No examples here, so nothing to suggest.
+            self.send(response(id, json!([])));
+            return;
+        };
+
+        // Only the latest request for a document is worth finishing.
+        self.cancel_document(uri);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let key = id.to_string();
+        self.jobs.lock().unwrap().insert(
+            key.clone(),
+            Job {
+                uri: uri.to_string(),
+                cancel: cancel.clone(),
+            },
+        );
+
+        let config = self.config.clone();
+        let jobs = self.jobs.clone();
+        let out = self.out.clone();
+
+        // Tasks aren't `Send`, so the job builds its own from the JSON.
+        std::thread::spawn(move || {
+            let message = match candidates(task, &config, cancel) {
+                Ok(Some(candidates)) => response(id, reply.render(candidates)),
+                Ok(None) => error(id, REQUEST_CANCELLED, "cancelled".to_string()),
+                Err(e) => error(id, INVALID_PARAMS, e.to_string()),
+            };
+
+            jobs.lock().unwrap().remove(&key);
+            let _ = out.send(message);
+        });
+    }
+
+    fn cancel_document(&self, uri: &str) {
+        for job in self.jobs.lock().unwrap().values() {
+            if job.uri == uri {
+                job.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn cancel_all(&self) {
+        for job in self.jobs.lock().unwrap().values() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A candidate, ready to be sent to the client.
+struct Candidate {
+    code: String,
+    ty: Type,
+    level: u8,
+}
+
+/// Runs the synthesizer on `task`, returning the best candidates first,
+/// or `None` if we were cancelled.
+fn candidates(
+    task: Value,
+    config: &Config,
+    cancel: Arc<AtomicBool>,
+) -> Result<Option<Vec<Candidate>>, Error> {
+    let task = loader::task_from_value(task)?;
+    let vocab = vocab::select(&config.vocab)?;
+
+    let mut limits = Limits::new(config.max_level).with_cancel(cancel);
+    if let Some(timeout) = config.timeout {
+        limits = limits.with_timeout(timeout);
+    }
+
+    let mut synth = Synthesizer::new(vocab, task);
+    let (solutions, stop) = synth.solutions(config.solutions, &limits, &synth::default_ranking);
+    if stop == Stop::Cancelled {
+        return Ok(None);
+    }
+
+    let store = synth.store();
+    Ok(Some(
+        solutions
+            .iter()
+            .map(|prog| Candidate {
+                code: prog.code(store),
+                ty: prog.ty(),
+                level: prog.level(store).into(),
+            })
+            .collect(),
+    ))
+}
+
+impl Reply {
+    fn render(self, candidates: Vec<Candidate>) -> Value {
+        match self {
+            Reply::Completion => candidates
+                .into_iter()
+                .enumerate()
+                .map(|(i, candidate)| {
+                    json!({
+                        "label": candidate.code,
+                        "kind": 15, // Snippet
+                        "detail": format!("{:?}, level {}", candidate.ty, candidate.level),
+                        "sortText": format!("{i:04}"),
+                        "insertText": candidate.code,
+                    })
+                })
+                .collect(),
+            Reply::CodeAction { uri, range } => candidates
+                .into_iter()
+                .map(|candidate| {
+                    json!({
+                        "title": format!("Insert `{}`", candidate.code),
+                        "kind": "refactor",
+                        "edit": {
+                            "changes": {
+                                uri.clone(): [{ "range": range, "newText": candidate.code }],
+                            },
+                        },
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    /// A server on another thread, and our ends of its input and output.
+    struct Client {
+        input: UnixStream,
+        output: BufReader<UnixStream>,
+        server: JoinHandle<()>,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let config = Config {
+                vocab: vec!["all".to_string()],
+                max_level: 255,
+                timeout: None,
+                solutions: 1,
+            };
+            let (input, server_input) = UnixStream::pair().unwrap();
+            let (output, server_output) = UnixStream::pair().unwrap();
+            let server = std::thread::spawn(move || {
+                serve(config, &mut BufReader::new(server_input), server_output).unwrap()
+            });
+            Self {
+                input,
+                output: BufReader::new(output),
+                server,
+            }
+        }
+
+        fn send_raw(&mut self, body: &str) {
+            write!(self.input, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        }
+
+        fn send(&mut self, message: Value) {
+            self.send_raw(&message.to_string());
+        }
+
+        fn receive(&mut self) -> Value {
+            let body = read_message(&mut self.output).unwrap().unwrap();
+            serde_json::from_slice(&body).unwrap()
+        }
+
+        fn exit(mut self) {
+            self.send(json!({ "jsonrpc": "2.0", "id": 0, "method": "shutdown" }));
+            assert_eq!(self.receive(), response(json!(0), Value::Null));
+            self.send(json!({ "jsonrpc": "2.0", "method": "exit" }));
+            self.server.join().unwrap();
+        }
+    }
+
+    fn completion(id: u64, task: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "textDocument/completion",
+            "params": {
+                "textDocument": { "uri": "file:///a.js" },
+                "position": { "line": 3, "character": 0 },
+                "task": task,
+            },
+        })
+    }
+
+    #[test]
+    fn completes_with_the_best_candidate() {
+        let mut client = Client::start();
+        client.send(json!({
+            "jsonrpc": "2.0",
+            "method": "sober/setExamples",
+            "params": {
+                "textDocument": { "uri": "file:///a.js" },
+                "position": { "line": 3, "character": 0 },
+                "task": { "examples": 2, "variables": { "x": [1, 2] }, "output": [2, 3] },
+            },
+        }));
+        client.send(completion(1, Value::Null));
+
+        let reply = client.receive();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"][0]["label"], "x + 1");
+        assert_eq!(reply["result"][0]["insertText"], "x + 1");
+        assert_eq!(reply["result"].as_array().unwrap().len(), 1);
+
+        // Lines without examples have nothing to suggest.
+        client.send(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/completion",
+            "params": {
+                "textDocument": { "uri": "file:///a.js" },
+                "position": { "line": 4, "character": 0 },
+            },
+        }));
+        assert_eq!(client.receive(), response(json!(2), json!([])));
+        client.exit();
+    }
+
+    #[test]
+    fn cancels_requests() {
+        let mut client = Client::start();
+        // Nothing builds a string out of this, so the search runs until it's cancelled.
+        let task = json!({ "examples": 1, "variables": { "x": [1] }, "output": ["no"] });
+        client.send(completion(1, task));
+        client
+            .send(json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": 1 } }));
+
+        let reply = client.receive();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["error"]["code"], REQUEST_CANCELLED);
+        client.exit();
+    }
+
+    #[test]
+    fn reports_malformed_messages_and_carries_on() {
+        let mut client = Client::start();
+        client.send_raw("{\"jsonrpc\": \"2.0\", \"id\": 1,");
+
+        let reply = client.receive();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+
+        client.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "frobnicate" }));
+        let reply = client.receive();
+        assert_eq!(reply["id"], 2);
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+        client.exit();
+    }
+}
@@ -1,50 +1,286 @@
-#![allow(dead_code)]
-#![feature(try_trait_v2)]
-#![feature(iterator_try_collect)]
-#![feature(ascii_char)]
-
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use sober::batch::{self, SummaryFormat};
+use sober::output::{self, Format};
+use sober::{daemon, loader, lsp, repl};
+use sober::{Error, Lang, Limits, Stop, Synthesizer, Vocab};
+
+/// Exit codes, besides 0 for when we found a solution.
+const EXIT_ERROR: u8 = 1;
+const EXIT_EXHAUSTED: u8 = 3;
+const EXIT_TIMED_OUT: u8 = 4;
+const EXIT_FAILS_TASK: u8 = 5;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Bottom-up synthesis of JavaScript snippets from examples"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search for programs that satisfy a task file.
+    ///
+    /// Exits with 0 if we found a solution, 3 if we searched every level
+    /// without finding one, and 4 if we ran out of time.
+    Synth {
+        #[command(flatten)]
+        search: SearchArgs,
+        /// How many solutions to look for.
+        #[arg(short = 'n', long, default_value_t = 1)]
+        solutions: usize,
+        #[arg(long, value_enum, default_value_t = Format::Plain)]
+        format: Format,
+    },
+    /// Print every program we enumerate for a task file as a line of JSON,
+    /// until we're out of levels or time.
+    Enumerate {
+        #[command(flatten)]
+        search: SearchArgs,
+    },
+    /// Check a JavaScript snippet against a task file without searching,
+    /// printing what it evaluates to and how it changes the variables
+    /// on each example.
+    ///
+    /// Exits with 0 if it passes every example, and 5 if it fails any.
+    Evaluate {
+        /// The JSON task file (see the `loader` module for the format).
+        task: PathBuf,
+        /// The snippet, e.g. `s[x.length - 1]`.
+        #[arg(allow_hyphen_values = true)]
+        code: String,
+        #[arg(long, value_enum, default_value_t = Format::Conditions)]
+        format: Format,
+    },
+    /// Build a task interactively, running the synthesizer as you go.
+    Repl {
+        #[command(flatten)]
+        bounds: Bounds,
+    },
+    /// Serve completions and code actions over the Language Server Protocol,
+    /// on stdin and stdout.
+    Lsp {
+        #[command(flatten)]
+        bounds: Bounds,
+        /// How many candidates to offer.
+        #[arg(short = 'n', long, default_value_t = 5)]
+        solutions: usize,
+    },
+    /// Serve synthesis requests on a Unix domain socket, keeping each task's
+    /// bank around between requests (see the `daemon` module for the protocol).
+    Daemon {
+        /// Where to put the socket.
+        socket: PathBuf,
+        #[command(flatten)]
+        bounds: Bounds,
+        /// How many banks to keep around.
+        #[arg(long, default_value_t = 16)]
+        max_sessions: usize,
+    },
+    /// Look for one solution to each task file in a directory,
+    /// and summarize how long it took, how far we got, and what we found.
+    /// The timeout applies to each task separately.
+    Batch {
+        /// The directory of JSON task files.
+        dir: PathBuf,
+        #[command(flatten)]
+        bounds: Bounds,
+        /// How many tasks to run in parallel.
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+        #[arg(long, value_enum, default_value_t = SummaryFormat::Csv)]
+        format: SummaryFormat,
+        /// Write the summary here instead of to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// The JSON task file (see the `loader` module for the format).
+    task: PathBuf,
+    #[command(flatten)]
+    bounds: Bounds,
+    /// Stop once the bank holds this many programs.
+    #[arg(long)]
+    max_bank_size: Option<usize>,
+    /// The language to write programs in.
+    #[arg(long, value_enum, default_value_t)]
+    lang: Lang,
+}
+
+#[derive(Args)]
+struct Bounds {
+    /// Comma-separated components to use: op names (e.g. `sum,str_len`),
+    /// groups (`int`, `str`, `array`), or `all`.
+    #[arg(long, value_delimiter = ',', default_value = "all")]
+    vocab: Vec<String>,
+    /// The last level to enumerate.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(1..))]
+    max_level: u8,
+    /// Give up after this many seconds.
+    #[arg(long)]
+    timeout: Option<f64>,
+}
+
+impl Bounds {
+    fn timeout(&self) -> Result<Option<Duration>, Error> {
+        Ok(self.timeout.map(Duration::try_from_secs_f64).transpose()?)
+    }
+
+    fn limits(&self) -> Result<Limits, Error> {
+        let limits = Limits::new(self.max_level);
+        Ok(match self.timeout()? {
+            Some(timeout) => limits.with_timeout(timeout),
+            None => limits,
+        })
+    }
+}
+
+impl SearchArgs {
+    fn synthesizer(&self) -> Result<(Synthesizer, Limits), Error> {
+        let task = loader::load_task(&self.task)?;
+        let vocab = Vocab::select(&self.bounds.vocab)?;
+        let mut limits = self.bounds.limits()?;
+        if let Some(size) = self.max_bank_size {
+            limits = limits.with_max_bank_size(size);
+        }
+        Ok((Synthesizer::new(vocab, task), limits))
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Error> {
+    let mut stdout = std::io::stdout().lock();
+
+    match cli.command {
+        Command::Synth {
+            search,
+            solutions,
+            format,
+        } => {
+            let (mut synth, limits) = search.synthesizer()?;
+            let (found, stop) = synth.best(solutions, &limits);
+            output::write_solutions(&mut stdout, format, search.lang, &synth, &found, stop)?;
+
+            Ok(match stop {
+                _ if !found.is_empty() => ExitCode::SUCCESS,
+                Stop::TimedOut | Stop::Cancelled => ExitCode::from(EXIT_TIMED_OUT),
+                Stop::Found | Stop::Exhausted | Stop::BankFull => ExitCode::from(EXIT_EXHAUSTED),
+            })
+        }
+        Command::Enumerate { search } => {
+            let (mut synth, limits) = search.synthesizer()?;
+
+            let mut programs = synth.programs(&limits);
+            while let Some(snippet) = programs.next() {
+                output::write_program_line(
+                    &mut stdout,
+                    programs.synth().get(snippet),
+                    search.lang,
+                )?;
+            }
+            let stop = programs
+                .stop()
+                .expect("we only run out of programs when we stop");
+            stdout.flush()?;
+
+            Ok(match stop {
+                Stop::TimedOut | Stop::Cancelled => ExitCode::from(EXIT_TIMED_OUT),
+                Stop::Found | Stop::Exhausted | Stop::BankFull => ExitCode::SUCCESS,
+            })
+        }
+        Command::Evaluate { task, code, format } => {
+            let task = loader::load_task(&task)?;
+            let evaluation = output::evaluate(&task, code.trim())?;
+            output::write_evaluation(&mut stdout, format, &evaluation)?;
+            Ok(if evaluation.passes() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(EXIT_FAILS_TASK)
+            })
+        }
+        Command::Repl { bounds } => {
+            Vocab::select(&bounds.vocab)?;
+            let timeout = bounds.timeout()?;
+            let mut session = repl::Session::new(bounds.vocab, bounds.max_level, timeout);
+            session.run(&mut std::io::stdin().lock(), &mut stdout)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Lsp { bounds, solutions } => {
+            Vocab::select(&bounds.vocab)?;
+            let config = lsp::Config {
+                timeout: bounds.timeout()?,
+                vocab: bounds.vocab,
+                max_level: bounds.max_level,
+                solutions,
+            };
+            drop(stdout);
+            lsp::serve(config, &mut std::io::stdin().lock(), std::io::stdout())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Daemon {
+            socket,
+            bounds,
+            max_sessions,
+        } => {
+            Vocab::select(&bounds.vocab)?;
+            let config = daemon::Config {
+                timeout: bounds.timeout()?,
+                vocab: bounds.vocab,
+                max_level: bounds.max_level,
+                max_sessions,
+            };
+            daemon::Daemon::new(config).serve(&socket)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Batch {
+            dir,
+            bounds,
+            jobs,
+            format,
+            output,
+        } => {
+            // Catch a bad vocab once, rather than in every task.
+            Vocab::select(&bounds.vocab)?;
+
+            let files = batch::task_files(&dir)?;
+            let outcomes = batch::run(
+                &files,
+                &bounds.vocab,
+                bounds.max_level,
+                bounds.timeout()?,
+                jobs,
+            );
+
+            match output {
+                Some(path) => {
+                    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                    batch::write_summary(&mut file, format, &outcomes)?;
+                }
+                None => batch::write_summary(&mut stdout, format, &outcomes)?,
+            }
 
-mod cond;
-mod ops;
-mod store;
-mod synth;
-mod task;
-mod utils;
-mod vocab;
-
-pub(crate) use cond::*;
-pub(crate) use ops::*;
-pub(crate) use store::*;
-pub(crate) use task::SynthesisTask;
-pub(crate) use utils::*;
-
-fn main() -> Result<(), utils::Error> {
-    let mut stdout = std::io::stdout();
-    let task = SynthesisTask::new(
-        [
-            ("x".to_string(), Anies::Int(vec![0, 2])),
-            ("y".to_string(), Anies::Int(vec![1, 1])),
-            (
-                "s".to_string(),
-                Anies::Str(vec!["a".to_string(), "asdfmovie".to_string()]),
-            ),
-        ]
-        .into(),
-        2,
-    );
-    let mut synth = synth::Synthesizer::new(vocab::vocab(), task);
-
-    loop {
-        let prog = synth.next();
-        let store = synth.store();
-        let code = prog.code(store);
-        let (pre, post) = prog.conditions(store);
-
-        pre.pretty_print(&mut stdout, store)?;
-        write!(stdout, "\n{code}\n")?;
-        post.pretty_print(&mut stdout, store)?;
-        write!(stdout, "\n\n")?;
-        stdout.flush()?;
+            Ok(ExitCode::SUCCESS)
+        }
     }
 }
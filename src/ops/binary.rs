@@ -1,12 +1,15 @@
-use super::Level;
 use super::Program;
+use super::{arg_code, Level};
+use crate::ast::Ast;
 use crate::cond::*;
 use crate::store;
 use crate::store::*;
 use crate::synth;
 use crate::synth::Enumerator;
+use crate::task::Component;
 use crate::utils::*;
-use crate::MaybeProgram;
+use crate::vocab::Op;
+use crate::{Evaluated, MaybeProgram};
 
 pub struct BinMaybeProgram<L, R, O>
 where
@@ -18,10 +21,12 @@ where
     post: PostCondition,
     lhs: PIdx<L>,
     rhs: PIdx<R>,
+    op: Op,
     code: BinCode,
     values: Option<Vec<O>>,
     pointer: Option<Pointer>,
     level: Level,
+    uses: Uses,
 }
 
 impl<L, R, O> BinMaybeProgram<L, R, O>
@@ -33,25 +38,30 @@ where
     Bank: Store<R>,
     Bank: Store<O>,
 {
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
     pub fn new(
         lhs: PIdx<L>,
         rhs: PIdx<R>,
         values: Vec<O>,
+        op: Op,
         code: BinCode,
         pre: PreCondition,
         post: PostCondition,
         pointer: Option<Pointer>,
         level: Level,
+        uses: Uses,
     ) -> Box<dyn MaybeProgram<O>> {
         Box::new(Self {
             lhs,
             rhs,
+            op,
             code,
             values: Some(values),
             pre,
             post,
             pointer,
             level,
+            uses,
         })
     }
 }
@@ -80,11 +90,13 @@ where
             self.lhs,
             self.rhs,
             values,
+            self.op,
             self.code,
             self.pre,
             self.post,
             self.pointer,
             self.level,
+            self.uses,
         )
     }
 
@@ -99,6 +111,61 @@ where
     fn post_condition(&self) -> &PostCondition {
         &self.post
     }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
+}
+
+/// Evaluates a binary component on `lhs` and `rhs`, and checks the result
+/// against the store's frame and component constraints.
+#[allow(clippy::too_many_arguments)]
+fn build<L, R, O>(
+    op: Op,
+    eval: BinEval<L, R, O>,
+    code: BinCode,
+    lhs_idx: PIdx<L>,
+    rhs_idx: PIdx<R>,
+    level: Level,
+    uses: Uses,
+    store: &mut Bank,
+) -> Option<Box<dyn MaybeProgram<O>>>
+where
+    L: Value,
+    R: Value,
+    O: Value,
+    Bank: Store<L>,
+    Bank: Store<R>,
+    Bank: Store<O>,
+{
+    if !store.allows(uses) {
+        return None;
+    }
+
+    let lhs = &store[lhs_idx];
+    let rhs = &store[rhs_idx];
+    let (pre, post) = Condition::sequence(lhs.conditions(), rhs.conditions())?;
+    let (values, mutation, pointer) = (*eval)(lhs.as_ref(), rhs.as_ref(), &post, store)?;
+
+    if mutation
+        .as_ref()
+        .is_some_and(|m| store.is_read_only(m.pointer()))
+    {
+        return None;
+    }
+
+    let post = match mutation {
+        Some(mutation) => mutation.apply(post, store),
+        None => post.clone(),
+    };
+
+    if !store.preserves_frame(&pre, &post) {
+        return None;
+    }
+
+    Some(BinMaybeProgram::new(
+        lhs_idx, rhs_idx, values, op, code, pre, post, pointer, level, uses,
+    ))
 }
 
 pub struct BinProgram<L, R, O>
@@ -111,10 +178,12 @@ where
     post: PostCondition,
     lhs: PIdx<L>,
     rhs: PIdx<R>,
+    op: Op,
     code: BinCode,
     values: VIdx<O>,
     pointer: Option<Pointer>,
     level: Level,
+    uses: Uses,
 }
 
 impl<L, R, O> BinProgram<L, R, O>
@@ -127,25 +196,30 @@ where
     Bank: Store<O>,
 {
     #[inline]
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
     pub fn new(
         lhs: PIdx<L>,
         rhs: PIdx<R>,
         values: VIdx<O>,
+        op: Op,
         code: BinCode,
         pre: PreCondition,
         post: PostCondition,
         pointer: Option<Pointer>,
         level: Level,
+        uses: Uses,
     ) -> Box<dyn Program<O>> {
         Box::new(Self {
             lhs,
             rhs,
+            op,
             code,
             values,
             pre,
             post,
             pointer,
             level,
+            uses,
         })
     }
 }
@@ -159,8 +233,8 @@ where
     fn code(&self, store: &Bank) -> String {
         let lhs = &store[self.lhs];
         let rhs = &store[self.rhs];
-        let lhs = lhs.code(store);
-        let rhs = rhs.code(store);
+        let lhs = arg_code(self.op, 0, lhs.code(store), lhs.precedence());
+        let rhs = arg_code(self.op, 1, rhs.code(store), rhs.precedence());
         (self.code)(&lhs, &rhs)
     }
 
@@ -184,14 +258,33 @@ where
     fn values_idx(&self) -> VIdx<O> {
         self.values
     }
+
+    fn op(&self) -> Option<Op> {
+        Some(self.op)
+    }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
+
+    fn size(&self, store: &Bank) -> usize {
+        1 + store[self.lhs].size(store) + store[self.rhs].size(store)
+    }
+
+    fn to_ast(&self, store: &Bank) -> Ast {
+        Ast::Op {
+            op: self.op,
+            args: vec![store[self.lhs].to_ast(store), store[self.rhs].to_ast(store)],
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        self.op.precedence()
+    }
 }
 
-pub type BinEval<L, R, O> = &'static dyn Fn(
-    &dyn Program<L>,
-    &dyn Program<R>,
-    &Condition,
-    &Bank,
-) -> Option<(Vec<O>, Option<Mutation>, Option<Pointer>)>;
+pub type BinEval<L, R, O> =
+    &'static dyn Fn(&dyn Program<L>, &dyn Program<R>, &Condition, &Bank) -> Evaluated<O>;
 pub type BinCode = &'static dyn Fn(&str, &str) -> String;
 
 #[derive(Clone)]
@@ -201,6 +294,7 @@ where
     R: Value,
     O: Value,
 {
+    op: Op,
     eval: BinEval<L, R, O>,
     code: BinCode,
 }
@@ -218,18 +312,37 @@ where
     MaxPIdx: store::MaxIdx<O>,
     AnyProg: From<PIdx<O>>,
 {
-    pub fn new(eval: BinEval<L, R, O>, code: BinCode) -> Self {
-        Self { eval, code }
+    pub fn new(op: Op, eval: BinEval<L, R, O>, code: BinCode) -> Self {
+        Self { op, eval, code }
+    }
+
+    pub fn op(&self) -> Op {
+        self.op
     }
 
-    pub fn into_enum(&self, level: Level, max_idx: MaxPIdx) -> Box<dyn Enumerator> {
+    /// Applies this component to `lhs` and `rhs`, adding the result to the store.
+    /// Returns the new program, or the equivalent one already in the store.
+    pub fn apply(&self, lhs: PIdx<L>, rhs: PIdx<R>, store: &mut Bank) -> Option<PIdx<O>> {
+        let uses = store[lhs].uses() | store[rhs].uses() | store.uses(&Component::Op(self.op));
+        let level = store[lhs].level().bin_next(store[rhs].level());
+        let maybe_program = build(self.op, self.eval, self.code, lhs, rhs, level, uses, store)?;
+
+        match store.put_program(maybe_program) {
+            Ok(idx) | Err(idx) => Some(idx),
+        }
+    }
+
+    /// `uses` are the constraint bits set by this component itself.
+    pub fn enumerator(&self, level: Level, max_idx: MaxPIdx, uses: Uses) -> Box<dyn Enumerator> {
         Box::new(BinEnumerator {
+            op: self.op,
             eval: self.eval,
             code: self.code,
             lhs_idx: 0.into(),
             rhs_idx: 0.into(),
             level,
             max_idx,
+            uses,
         })
     }
 }
@@ -243,12 +356,14 @@ where
     Bank: Store<R>,
     Bank: Store<O>,
 {
+    op: Op,
     eval: BinEval<L, R, O>,
     code: BinCode,
     lhs_idx: PIdx<L>,
     rhs_idx: PIdx<R>,
     level: Level,
     max_idx: MaxPIdx,
+    uses: Uses,
 }
 
 impl<L, R, O> Enumerator for BinEnumerator<L, R, O>
@@ -264,6 +379,12 @@ where
     MaxPIdx: MaxIdx<R>,
 {
     fn next(&mut self, store: &mut Bank) -> synth::Result<AnyProg> {
+        // Either side may have no programs at all, e.g. strings in a task
+        // without string variables or constants.
+        if !self.max_idx.check(self.lhs_idx) || !self.max_idx.check(PIdx::<R>::from(0)) {
+            return synth::Result::Done;
+        }
+
         if !self.max_idx.check(self.rhs_idx) {
             if !self.max_idx.check(self.lhs_idx + 1) {
                 // We're out of programs
@@ -286,26 +407,26 @@ where
             return synth::Result::None;
         }
 
-        let (pre, post) = Condition::sequence(lhs.conditions(), rhs.conditions())?;
-        let (values, mutation, pointer) = (*self.eval)(lhs.as_ref(), rhs.as_ref(), &post, &store)?;
-
-        let post = match mutation {
-            Some(mutation) => mutation.apply(post, store),
-            None => post.clone(),
-        };
+        let uses = lhs.uses() | rhs.uses() | self.uses;
 
-        let maybe_program = BinMaybeProgram::new(
+        let maybe_program = build(
+            self.op,
+            self.eval,
+            self.code,
             self.lhs_idx,
             self.rhs_idx - 1,
-            values,
-            self.code,
-            pre,
-            post,
-            pointer,
             self.level,
-        );
+            uses,
+            store,
+        )?;
         let rs = store.put_program(maybe_program)?;
 
+        // Programs missing a required component still make it into the store,
+        // since larger programs may add it, but they're not candidates.
+        if !store.satisfies(uses) {
+            return synth::Result::None;
+        }
+
         synth::Result::Some(rs.into())
     }
 }
@@ -1,11 +1,15 @@
 use crate::*;
 
-use self::store::{Bank, Store};
+use self::store::{Bank, Store, Uses};
+use self::vocab::{Op, ATOM};
 
 pub struct Constant<T: Value> {
     name: String,
+    /// The constant's value on every example.
+    value: Any,
     values: VIdx<T>,
     condition: Condition,
+    uses: Uses,
 }
 
 impl<T> Constant<T>
@@ -13,11 +17,20 @@ where
     T: Value,
     Bank: Store<T>,
 {
-    pub fn new(name: String, values: VIdx<T>, vars: usize) -> Box<dyn Program<T>> {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        name: String,
+        value: Any,
+        values: VIdx<T>,
+        vars: usize,
+        uses: Uses,
+    ) -> Box<dyn Program<T>> {
         Box::new(Self {
             name,
+            value,
             values,
             condition: Condition::empty(vars),
+            uses,
         })
     }
 }
@@ -50,4 +63,28 @@ where
     fn values_idx(&self) -> VIdx<T> {
         self.values
     }
+
+    fn op(&self) -> Option<Op> {
+        None
+    }
+
+    fn size(&self, _: &Bank) -> usize {
+        1
+    }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
+
+    fn to_ast(&self, _: &Bank) -> Ast {
+        Ast::Literal(self.value.clone())
+    }
+
+    fn precedence(&self) -> u8 {
+        match self.value {
+            // `-1` is the minus of `1`, as far as parsing goes.
+            Any::Int(value) if value < 0 => Op::Minus.precedence(),
+            _ => ATOM,
+        }
+    }
 }
@@ -1,7 +1,9 @@
 use std::cmp::max;
 
+use crate::ast::Ast;
 use crate::cond::*;
-use crate::store::Bank;
+use crate::store::{Bank, Uses};
+use crate::vocab::Op;
 use crate::*;
 
 mod binary;
@@ -10,10 +12,15 @@ mod nullary;
 mod unary;
 mod variable;
 
-pub(crate) use binary::{BinBuilder, BinEnumerator, BinMaybeProgram, BinProgram};
+pub(crate) use binary::BinBuilder;
 pub(crate) use constant::Constant;
-pub(crate) use unary::{UniBuilder, UniMaybeProgram, UniProgram};
-pub(crate) use variable::{MaybeVariable, Variable};
+pub(crate) use unary::UniBuilder;
+pub(crate) use variable::Variable;
+
+/// What evaluating a component gives: its values, one per example, how it
+/// changes the variables, and the variable its result refers to.
+/// `None` if it has no value on some example.
+pub type Evaluated<T> = Option<(Vec<T>, Option<Mutation>, Option<Pointer>)>;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Debug)]
 pub struct Level(u8);
@@ -31,20 +38,23 @@ impl From<Level> for u8 {
 }
 
 impl Level {
+    /// The level of a program built on one at this level.
+    /// Programs past the last level stay at the last level.
     pub fn next(&self) -> Self {
-        Self(self.0 + 1)
+        Self(self.0.saturating_add(1))
     }
 
     pub fn prev(&self) -> Self {
         Self(self.0 - 1)
     }
 
-    pub fn inc(&mut self) {
-        self.0 += 1;
+    /// The level after this one, unless this is the last.
+    pub fn checked_next(&self) -> Option<Self> {
+        self.0.checked_add(1).map(Self)
     }
 
     pub fn bin_next(&self, rhs: Self) -> Self {
-        Self(max(self.0, rhs.0) + 1)
+        max(*self, rhs).next()
     }
 }
 
@@ -57,6 +67,7 @@ where
     fn pointer(&self) -> Option<Pointer>;
     fn pre_condition(&self) -> &PreCondition;
     fn post_condition(&self) -> &PostCondition;
+    fn uses(&self) -> Uses;
     fn into_program(self: Box<Self>, values: VIdx<T>) -> Box<dyn Program<T>>;
 }
 
@@ -67,4 +78,32 @@ pub trait Program<T: Value> {
     fn conditions(&self) -> (&PreCondition, &PostCondition);
     fn pointer(&self) -> Option<Pointer>;
     fn level(&self) -> Level;
+
+    /// The component at the root of this program,
+    /// or `None` for variables and constants.
+    fn op(&self) -> Option<Op>;
+
+    /// The number of nodes in this program.
+    fn size(&self, store: &Bank) -> usize;
+
+    /// The constrained components this program uses.
+    fn uses(&self) -> Uses;
+
+    /// An owned copy of the program as a tree.
+    fn to_ast(&self, store: &Bank) -> Ast;
+
+    /// How tightly the program's code holds together, see `Op::precedence`.
+    fn precedence(&self) -> u8;
+}
+
+/// The code for the `i`th argument of `op`, in parentheses if it would
+/// otherwise parse differently: `x - (y + z)` or `-(x + y)`.
+pub(crate) fn arg_code(op: Op, i: usize, code: String, precedence: u8) -> String {
+    // `-` in front of `-x` would make `--x`, which is a decrement.
+    let merges = op == Op::Minus && code.starts_with('-');
+    if precedence < op.arg_precedence(i) || merges {
+        format!("({code})")
+    } else {
+        code
+    }
 }
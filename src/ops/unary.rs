@@ -2,17 +2,16 @@ use crate::cond::*;
 use crate::store::*;
 use crate::synth;
 use crate::synth::Enumerator;
+use crate::task::Component;
 use crate::utils::*;
-use crate::MaybeProgram;
+use crate::vocab::Op;
+use crate::{Evaluated, MaybeProgram};
 
-use super::Level;
 use super::Program;
+use super::{arg_code, Level};
+use crate::ast::Ast;
 
-pub type UniEval<I, O> = &'static dyn Fn(
-    &dyn Program<I>,
-    &Condition,
-    &Bank,
-) -> Option<(Vec<O>, Option<Mutation>, Option<Pointer>)>;
+pub type UniEval<I, O> = &'static dyn Fn(&dyn Program<I>, &Condition, &Bank) -> Evaluated<O>;
 pub type UniCode = &'static dyn Fn(&str) -> String;
 
 #[derive(Clone)]
@@ -21,6 +20,7 @@ where
     I: Value,
     O: Value,
 {
+    op: Op,
     eval: UniEval<I, O>,
     code: UniCode,
 }
@@ -34,17 +34,36 @@ where
     PIdx<O>: Into<AnyProg>,
     MaxPIdx: MaxIdx<I>,
 {
-    pub fn new(eval: UniEval<I, O>, code: UniCode) -> Self {
-        Self { eval, code }
+    pub fn new(op: Op, eval: UniEval<I, O>, code: UniCode) -> Self {
+        Self { op, eval, code }
     }
 
-    pub fn into_enum(&self, level: Level, max_idx: MaxPIdx) -> Box<dyn Enumerator> {
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    /// Applies this component to `arg`, adding the result to the store.
+    /// Returns the new program, or the equivalent one already in the store.
+    pub fn apply(&self, arg: PIdx<I>, store: &mut Bank) -> Option<PIdx<O>> {
+        let uses = store[arg].uses() | store.uses(&Component::Op(self.op));
+        let level = store[arg].level().next();
+        let maybe_program = build(self.op, self.eval, self.code, arg, level, uses, store)?;
+
+        match store.put_program(maybe_program) {
+            Ok(idx) | Err(idx) => Some(idx),
+        }
+    }
+
+    /// `uses` are the constraint bits set by this component itself.
+    pub fn enumerator(&self, level: Level, max_idx: MaxPIdx, uses: Uses) -> Box<dyn Enumerator> {
         Box::new(UniEnumerator {
+            op: self.op,
             eval: self.eval,
             code: self.code,
             arg_idx: 0.into(),
             level,
             max_idx,
+            uses,
         })
     }
 }
@@ -68,11 +87,13 @@ where
     Bank: Store<I>,
     Bank: Store<O>,
 {
+    op: Op,
     eval: UniEval<I, O>,
     code: UniCode,
     arg_idx: PIdx<I>,
     level: Level,
     max_idx: MaxPIdx,
+    uses: Uses,
 }
 
 impl<I, O> Enumerator for UniEnumerator<I, O>
@@ -104,27 +125,75 @@ where
             (prog, self.arg_idx - 1)
         };
 
-        let (pre, post) = prog.conditions();
-        let (values, mutation, pointer) = (self.eval)(prog.as_ref(), post, &store)?;
-        let pre = pre.clone(); // TODO Would be nice to avoid this clone if OE denies this program.
-
-        // If it comes with a mutation, we need to see if we can add it to the store.
-        // Basically, if it's a new variable that doesn't currently exist,
-        // this is guaranteed to be a new program. Otherwise, it will return the index to that value.
-        // Either way, we can proceed.
-        let post = match mutation {
-            Some(mutation) => mutation.apply(post.clone(), store),
-            None => post.clone(),
-        };
+        let uses = prog.uses() | self.uses;
 
         // See if we can add this
-        let maybe_program =
-            UniMaybeProgram::new(curr_idx, values, self.code, pre, post, pointer, self.level);
+        let maybe_program = build(
+            self.op, self.eval, self.code, curr_idx, self.level, uses, store,
+        )?;
         let prog_idx = store.put_program(maybe_program)?;
+
+        // Programs missing a required component still make it into the store,
+        // since larger programs may add it, but they're not candidates.
+        if !store.satisfies(uses) {
+            return synth::Result::None;
+        }
+
         synth::Result::Some(prog_idx.into())
     }
 }
 
+/// Evaluates a unary component on `arg`, and checks the result against
+/// the store's frame and component constraints.
+fn build<I, O>(
+    op: Op,
+    eval: UniEval<I, O>,
+    code: UniCode,
+    arg: PIdx<I>,
+    level: Level,
+    uses: Uses,
+    store: &mut Bank,
+) -> Option<Box<dyn MaybeProgram<O>>>
+where
+    I: Value,
+    O: Value,
+    Bank: Store<I>,
+    Bank: Store<O>,
+{
+    if !store.allows(uses) {
+        return None;
+    }
+
+    let prog = &store[arg];
+    let (pre, post) = prog.conditions();
+    let (values, mutation, pointer) = (eval)(prog.as_ref(), post, store)?;
+
+    if mutation
+        .as_ref()
+        .is_some_and(|m| store.is_read_only(m.pointer()))
+    {
+        return None;
+    }
+    let pre = pre.clone(); // TODO Would be nice to avoid this clone if OE denies this program.
+
+    // If it comes with a mutation, we need to see if we can add it to the store.
+    // Basically, if it's a new variable that doesn't currently exist,
+    // this is guaranteed to be a new program. Otherwise, it will return the index to that value.
+    // Either way, we can proceed.
+    let post = match mutation {
+        Some(mutation) => mutation.apply(post.clone(), store),
+        None => post.clone(),
+    };
+
+    if !store.preserves_frame(&pre, &post) {
+        return None;
+    }
+
+    Some(UniMaybeProgram::new(
+        arg, values, op, code, pre, post, pointer, level, uses,
+    ))
+}
+
 pub struct UniMaybeProgram<I, O>
 where
     I: Value,
@@ -133,10 +202,12 @@ where
     pre: PreCondition,
     post: PostCondition,
     arg: PIdx<I>,
+    op: Op,
     code: UniCode,
     values: Option<Vec<O>>,
     pointer: Option<Pointer>,
     level: Level,
+    uses: Uses,
 }
 
 impl<I, O> UniMaybeProgram<I, O>
@@ -146,23 +217,28 @@ where
     Bank: Store<I>,
     Bank: Store<O>,
 {
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
     pub fn new(
         arg: PIdx<I>,
         values: Vec<O>,
+        op: Op,
         code: UniCode,
         pre: PreCondition,
         post: PostCondition,
         pointer: Option<Pointer>,
         level: Level,
+        uses: Uses,
     ) -> Box<dyn MaybeProgram<O>> {
         Box::new(Self {
             arg,
+            op,
             code,
             values: Some(values),
             pre,
             post,
             pointer,
             level,
+            uses,
         })
     }
 }
@@ -188,11 +264,13 @@ where
         UniProgram::new(
             self.arg,
             values,
+            self.op,
             self.code,
             self.pre,
             self.post,
             self.pointer,
             self.level,
+            self.uses,
         )
     }
 
@@ -207,6 +285,10 @@ where
     fn post_condition(&self) -> &PostCondition {
         &self.post
     }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
 }
 
 pub struct UniProgram<L, O>
@@ -217,10 +299,12 @@ where
     pre: PreCondition,
     post: PostCondition,
     arg: PIdx<L>,
+    op: Op,
     code: UniCode,
     values: VIdx<O>,
     pointer: Option<Pointer>,
     level: Level,
+    uses: Uses,
 }
 
 impl<L, O> UniProgram<L, O>
@@ -231,23 +315,28 @@ where
     Bank: Store<O>,
 {
     #[inline]
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
     pub fn new(
         arg: PIdx<L>,
         values: VIdx<O>,
+        op: Op,
         code: UniCode,
         pre: PreCondition,
         post: PostCondition,
         pointer: Option<Pointer>,
         level: Level,
+        uses: Uses,
     ) -> Box<dyn Program<O>> {
         Box::new(Self {
             arg,
+            op,
             code,
             values,
             pre,
             post,
             pointer,
             level,
+            uses,
         })
     }
 }
@@ -260,7 +349,8 @@ where
     Bank: Store<O>,
 {
     fn code(&self, store: &Bank) -> String {
-        let arg = store[self.arg].code(&store);
+        let arg = &store[self.arg];
+        let arg = arg_code(self.op, 0, arg.code(store), arg.precedence());
         (self.code)(&arg)
     }
 
@@ -284,4 +374,27 @@ where
     fn values_idx(&self) -> VIdx<O> {
         self.values
     }
+
+    fn op(&self) -> Option<Op> {
+        Some(self.op)
+    }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
+
+    fn size(&self, store: &Bank) -> usize {
+        1 + store[self.arg].size(store)
+    }
+
+    fn to_ast(&self, store: &Bank) -> Ast {
+        Ast::Op {
+            op: self.op,
+            args: vec![store[self.arg].to_ast(store)],
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        self.op.precedence()
+    }
 }
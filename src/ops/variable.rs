@@ -1,6 +1,8 @@
+use crate::ast::Ast;
 use crate::cond::*;
 use crate::store::*;
 use crate::utils::*;
+use crate::vocab::{Op, ATOM};
 
 use super::Program;
 
@@ -9,39 +11,49 @@ pub struct MaybeVariable<T: Value> {
     values: Option<Vec<T>>,
     pointer: Pointer,
     variables: usize,
+    uses: Uses,
 }
 
 impl<T: Value> MaybeVariable<T> {
-    pub fn new(name: String, values: Vec<T>, pointer: Pointer, variables: usize) -> Self {
+    pub fn new(
+        name: String,
+        values: Vec<T>,
+        pointer: Pointer,
+        variables: usize,
+        uses: Uses,
+    ) -> Self {
         Self {
             name,
             values: Some(values),
             variables,
             pointer,
+            uses,
         }
     }
 }
 
 impl MaybeVariable<Int> {
-    pub fn into_program(self: Box<Self>, values: VIdx<Int>) -> Box<dyn Program<Int>> {
+    pub fn into_program(self, values: VIdx<Int>) -> Box<dyn Program<Int>> {
         Box::new(Variable {
             name: self.name,
             values,
             cond: Condition::empty(self.variables)
                 .mutate_with_index(self.pointer, Some(AnyVal::Int(values))),
             pointer: self.pointer,
+            uses: self.uses,
         })
     }
 }
 
 impl MaybeVariable<Str> {
-    pub fn into_program(self: Box<Self>, values: VIdx<Str>) -> Box<dyn Program<Str>> {
+    pub fn into_program(self, values: VIdx<Str>) -> Box<dyn Program<Str>> {
         Box::new(Variable {
             name: self.name,
             values,
             cond: Condition::empty(self.variables)
                 .mutate_with_index(self.pointer, Some(AnyVal::Str(values))),
             pointer: self.pointer,
+            uses: self.uses,
         })
     }
 }
@@ -51,10 +63,17 @@ pub struct Variable<T: Value> {
     values: VIdx<T>,
     cond: Condition,
     pointer: Pointer,
+    uses: Uses,
 }
 
 impl Variable<Str> {
-    pub fn new(name: String, values: VIdx<Str>, pointer: Pointer, variables: usize) -> Box<Self> {
+    pub fn new(
+        name: String,
+        values: VIdx<Str>,
+        pointer: Pointer,
+        variables: usize,
+        uses: Uses,
+    ) -> Box<Self> {
         let cond =
             Condition::empty(variables).mutate_with_index(pointer, Some(AnyVal::Str(values)));
         Box::new(Self {
@@ -62,12 +81,19 @@ impl Variable<Str> {
             values,
             cond,
             pointer,
+            uses,
         })
     }
 }
 
 impl Variable<Int> {
-    pub fn new(name: String, values: VIdx<Int>, pointer: Pointer, variables: usize) -> Box<Self> {
+    pub fn new(
+        name: String,
+        values: VIdx<Int>,
+        pointer: Pointer,
+        variables: usize,
+        uses: Uses,
+    ) -> Box<Self> {
         let cond =
             Condition::empty(variables).mutate_with_index(pointer, Some(AnyVal::Int(values)));
         Box::new(Self {
@@ -75,6 +101,7 @@ impl Variable<Int> {
             values,
             cond,
             pointer,
+            uses,
         })
     }
 }
@@ -85,6 +112,7 @@ impl Variable<IntArray> {
         values: VIdx<IntArray>,
         pointer: Pointer,
         variables: usize,
+        uses: Uses,
     ) -> Box<Self> {
         let cond =
             Condition::empty(variables).mutate_with_index(pointer, Some(AnyVal::IntArray(values)));
@@ -93,6 +121,7 @@ impl Variable<IntArray> {
             values,
             cond,
             pointer,
+            uses,
         })
     }
 }
@@ -124,4 +153,24 @@ where
     fn values_idx(&self) -> VIdx<T> {
         self.values
     }
+
+    fn op(&self) -> Option<Op> {
+        None
+    }
+
+    fn size(&self, _: &Bank) -> usize {
+        1
+    }
+
+    fn uses(&self) -> Uses {
+        self.uses
+    }
+
+    fn to_ast(&self, _: &Bank) -> Ast {
+        Ast::Var(self.name.clone())
+    }
+
+    fn precedence(&self) -> u8 {
+        ATOM
+    }
 }
@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::ast::Ast;
+use crate::lang::Lang;
+use crate::parse::{self, ParseError};
+use crate::synth::{Snippet, SnippetRef, Stop, Synthesizer};
+use crate::task::{Component, Mismatch, SynthesisTask};
+use crate::utils::*;
+use crate::vocab::Vocab;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Format {
+    /// Just the code, one solution per line.
+    Plain,
+    /// The code between its pre- and post-conditions.
+    Conditions,
+    /// A single JSON object with the status and solutions.
+    Json,
+}
+
+/// The code in the language we were asked for, or why there's none.
+#[derive(Serialize)]
+struct Code {
+    code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unsupported: Option<String>,
+}
+
+impl Code {
+    fn new(snippet: SnippetRef, lang: Lang) -> Self {
+        match snippet.render(lang) {
+            Ok(code) => Self {
+                code: Some(code),
+                unsupported: None,
+            },
+            Err(e) => Self {
+                code: None,
+                unsupported: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Solution {
+    #[serde(flatten)]
+    code: Code,
+    #[serde(rename = "type")]
+    ty: Type,
+    level: u8,
+    size: usize,
+}
+
+/// Everything we know about an enumerated program,
+/// with variables referred to by name.
+#[derive(Serialize)]
+struct Enumerated<'s> {
+    #[serde(flatten)]
+    code: Code,
+    #[serde(rename = "type")]
+    ty: Type,
+    level: u8,
+    pointer: Option<&'s str>,
+    values: Anies,
+    ast: Ast,
+    pre: BTreeMap<String, Anies>,
+    post: BTreeMap<String, Anies>,
+    solution: bool,
+}
+
+/// How a program does on one example.
+#[derive(Serialize)]
+struct ExampleResult {
+    example: usize,
+    /// What it evaluates to, or `None` if it has no value here.
+    result: Option<Any>,
+    /// The values of the variables it reads, before and after.
+    pre: BTreeMap<String, Any>,
+    post: BTreeMap<String, Any>,
+    /// The variables it changes, by name.
+    changed: BTreeMap<String, Change>,
+    mismatches: Vec<Mismatch>,
+}
+
+#[derive(Serialize)]
+struct Change {
+    before: Any,
+    after: Any,
+}
+
+/// A program we were given, rather than found, checked against the task
+/// one example at a time, see `evaluate`.
+#[derive(Serialize)]
+pub struct Evaluation {
+    code: String,
+    #[serde(rename = "type")]
+    ty: Type,
+    examples: Vec<ExampleResult>,
+    /// The examples it fails, by index.
+    failed: Vec<usize>,
+}
+
+impl Evaluation {
+    /// Whether it passes every example.
+    pub fn passes(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    status: Stop,
+    solutions: Vec<Solution>,
+}
+
+/// Writes the solutions we found, and why we stopped, in `format`,
+/// with their code in `lang`.
+/// Solutions that can't be written in `lang` are skipped with a warning,
+/// except in the `json` format, which says why they have no code.
+pub fn write_solutions(
+    out: &mut dyn Write,
+    format: Format,
+    lang: Lang,
+    synth: &Synthesizer,
+    solutions: &[Snippet],
+    stop: Stop,
+) -> std::io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer(&mut *out, &report(synth, solutions, stop, lang))?;
+        writeln!(out)?;
+        return out.flush();
+    }
+
+    for snippet in solutions {
+        let snippet = synth.get(*snippet);
+        let code = match snippet.render(lang) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("warning: skipping `{}`: {e}", snippet.code());
+                continue;
+            }
+        };
+
+        if format == Format::Conditions {
+            snippet.pretty_print_code(out, &code)?;
+            write!(out, "\n\n")?;
+        } else {
+            writeln!(out, "{code}")?;
+        }
+    }
+
+    out.flush()
+}
+
+/// The status and solutions, as the `json` format writes them.
+pub fn report(
+    synth: &Synthesizer,
+    solutions: &[Snippet],
+    stop: Stop,
+    lang: Lang,
+) -> serde_json::Value {
+    let report = Report {
+        status: stop,
+        solutions: solutions
+            .iter()
+            .map(|snippet| {
+                let snippet = synth.get(*snippet);
+                Solution {
+                    code: Code::new(snippet, lang),
+                    ty: snippet.ty(),
+                    level: snippet.level(),
+                    size: snippet.size(),
+                }
+            })
+            .collect(),
+    };
+    serde_json::to_value(report).expect("reports are valid JSON")
+}
+
+/// Writes `snippet` as a single line of JSON, with its code in `lang`.
+pub fn write_program_line(
+    out: &mut dyn Write,
+    snippet: SnippetRef,
+    lang: Lang,
+) -> std::io::Result<()> {
+    let line = Enumerated {
+        code: Code::new(snippet, lang),
+        ty: snippet.ty(),
+        level: snippet.level(),
+        pointer: snippet.pointer(),
+        values: snippet.values(),
+        ast: snippet.to_ast(),
+        pre: snippet.pre_condition(),
+        post: snippet.post_condition(),
+        solution: snippet.is_solution(),
+    };
+
+    serde_json::to_writer(&mut *out, &line)?;
+    writeln!(out)
+}
+
+/// Parses `code` and runs it on each example of `task` on its own, so
+/// that it can fail some examples without failing the rest. Unlike
+/// `Synthesizer::parse`, programs that have no value on some example or
+/// break the task's constraints are still evaluated, with those counted
+/// as mismatches. Predicates aren't checked.
+pub fn evaluate(task: &SynthesisTask, code: &str) -> Result<Evaluation, ParseError> {
+    let before = task.before_state();
+    let (ast, ty) = parse::parse_typed(code, &|name| before.get(name).map(Anies::ty))?;
+
+    let examples: Vec<ExampleResult> = (0..task.examples())
+        .map(|example| evaluate_example(task, &ast, example))
+        .collect();
+
+    Ok(Evaluation {
+        code: code.to_string(),
+        ty,
+        failed: examples
+            .iter()
+            .filter(|example| !example.mismatches.is_empty())
+            .map(|example| example.example)
+            .collect(),
+        examples,
+    })
+}
+
+fn evaluate_example(task: &SynthesisTask, ast: &Ast, example: usize) -> ExampleResult {
+    let mut mismatches = Vec::new();
+    let mut result = ExampleResult {
+        example,
+        result: None,
+        pre: BTreeMap::new(),
+        post: BTreeMap::new(),
+        changed: BTreeMap::new(),
+        mismatches: Vec::new(),
+    };
+
+    let mut synth = Synthesizer::new(Vocab::all(), task.unconstrained_example(example));
+    match synth.eval(ast) {
+        Ok(snippet) => {
+            let snippet = synth.get(snippet);
+            let first = |cond: BTreeMap<String, Anies>| {
+                cond.into_iter()
+                    .map(|(var, values)| (var, values.get(0)))
+                    .collect::<BTreeMap<_, _>>()
+            };
+            let value = snippet.values().get(0);
+            let after = first(snippet.after_state());
+
+            mismatches = task.mismatches(example, &value, &after);
+            result.result = Some(value);
+            result.pre = first(snippet.pre_condition());
+            result.post = first(snippet.post_condition());
+            result.changed = after
+                .into_iter()
+                .filter_map(|(var, after)| {
+                    let before = task.before_state()[&var].get(example);
+                    (before != after).then_some((var, Change { before, after }))
+                })
+                .collect();
+        }
+        Err(_) => mismatches.push(Mismatch::Undefined),
+    }
+
+    mismatches.extend(task.component_mismatches(ast));
+    result.mismatches = mismatches;
+    result
+}
+
+/// Writes `evaluation`: what the program evaluates to on each example,
+/// which variables it changes, and how it fails the task, if it does.
+/// The `conditions` format starts with the code between the values of the
+/// variables it reads, before and after, and `plain` leaves them out.
+pub fn write_evaluation(
+    out: &mut dyn Write,
+    format: Format,
+    evaluation: &Evaluation,
+) -> std::io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer(&mut *out, evaluation)?;
+        writeln!(out)?;
+        return out.flush();
+    }
+
+    if format == Format::Conditions {
+        write_condition(out, &evaluation.examples, |example| &example.pre)?;
+        write!(out, "\n{}\n", evaluation.code)?;
+        write_condition(out, &evaluation.examples, |example| &example.post)?;
+        write!(out, "\n\n")?;
+    }
+
+    for example in &evaluation.examples {
+        let status = if example.mismatches.is_empty() {
+            "ok"
+        } else {
+            "fails"
+        };
+        writeln!(out, "example {}: {status}", example.example)?;
+
+        let mut result = example
+            .result
+            .as_ref()
+            .map_or("undefined".to_string(), show);
+        for mismatch in &example.mismatches {
+            match mismatch {
+                Mismatch::Output { expected, .. } => {
+                    result += &format!(", expected {}", show(expected))
+                }
+                Mismatch::ForbiddenOutput { .. } => result += ", which is forbidden",
+                _ => (),
+            }
+        }
+        writeln!(out, "  result: {result}")?;
+
+        // The variables it changes, and the ones it should have.
+        let mut vars: BTreeMap<&str, String> = example
+            .changed
+            .iter()
+            .map(|(var, change)| {
+                let line = format!("{} -> {}", show(&change.before), show(&change.after));
+                (var.as_str(), line)
+            })
+            .collect();
+        for mismatch in &example.mismatches {
+            let (var, actual, note) = match mismatch {
+                Mismatch::After {
+                    var,
+                    expected,
+                    actual,
+                } => (var, actual, format!(", expected {}", show(expected))),
+                Mismatch::ForbiddenAfter { var, value } => {
+                    (var, value, ", which is forbidden".to_string())
+                }
+                Mismatch::ReadOnly { var } => {
+                    let actual = &example.changed[var].after;
+                    (var, actual, ", but it's read-only".to_string())
+                }
+                _ => continue,
+            };
+            *vars.entry(var).or_insert_with(|| show(actual)) += &note;
+        }
+        for (var, line) in vars {
+            writeln!(out, "  {var}: {line}")?;
+        }
+
+        for mismatch in &example.mismatches {
+            match mismatch {
+                Mismatch::Forbidden { component } => {
+                    writeln!(out, "  uses {}, which is forbidden", describe(component))?
+                }
+                Mismatch::Missing { component } => writeln!(
+                    out,
+                    "  doesn't use {}, which is required",
+                    describe(component)
+                )?,
+                _ => (),
+            }
+        }
+    }
+
+    let examples = evaluation.examples.len();
+    match evaluation.failed.len() {
+        0 => writeln!(out, "passes every example")?,
+        failed => writeln!(out, "fails {failed} of {examples} examples")?,
+    }
+    out.flush()
+}
+
+/// Writes the variables in `condition` of each example, with `_` for the
+/// examples that don't have them, like `Condition::pretty_print`.
+fn write_condition(
+    out: &mut dyn Write,
+    examples: &[ExampleResult],
+    condition: fn(&ExampleResult) -> &BTreeMap<String, Any>,
+) -> std::io::Result<()> {
+    let vars: BTreeSet<&String> = examples
+        .iter()
+        .flat_map(|example| condition(example).keys())
+        .collect();
+
+    write!(out, "{{ ")?;
+    for var in vars {
+        let values: Vec<String> = examples
+            .iter()
+            .map(|example| condition(example).get(var).map_or("_".to_string(), show))
+            .collect();
+        write!(out, "{var} -> [{}], ", values.join(", "))?;
+    }
+    write!(out, " }}")
+}
+
+/// A single value, written like `Condition::pretty_print` writes them.
+fn show(value: &Any) -> String {
+    match value {
+        Any::Int(value) => format!("{value:?}"),
+        Any::Str(value) => format!("{value:?}"),
+        Any::IntArray(value) => format!("{value:?}"),
+    }
+}
+
+fn describe(component: &Component) -> String {
+    match component {
+        Component::Var(name) | Component::Const(name) => format!("`{name}`"),
+        Component::Op(op) => format!("`{}`", op.name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::loader;
+
+    fn program_line(code: &str) -> Value {
+        let task = loader::parse_task(
+            r#"{"examples":2,"variables":{"x":[1,2],"y":[5,6]},"after":{"x":[2,3]}}"#,
+        )
+        .unwrap();
+        let mut synth = Synthesizer::new(Vocab::all(), task);
+        let snippet = synth.parse(code).unwrap();
+
+        let mut out = Vec::new();
+        write_program_line(&mut out, synth.get(snippet), Lang::Js).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        serde_json::from_str(&out).unwrap()
+    }
+
+    #[test]
+    fn writes_programs_as_json_lines() {
+        assert_eq!(
+            program_line("x++"),
+            json!({
+                "code": "x++",
+                "type": "Int",
+                "level": 1,
+                "pointer": null,
+                "values": [1, 2],
+                "ast": { "op": { "op": "inc", "args": [{ "var": "x" }] } },
+                "pre": { "x": [1, 2] },
+                "post": { "x": [2, 3] },
+                "solution": true,
+            })
+        );
+
+        let line = program_line("y + 1");
+        assert_eq!(line["code"], "y + 1");
+        assert_eq!(line["values"], json!([6, 7]));
+        assert_eq!(line["pre"], json!({ "y": [5, 6] }));
+        assert_eq!(line["post"], json!({ "y": [5, 6] }));
+        assert_eq!(line["solution"], false);
+    }
+}
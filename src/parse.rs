@@ -0,0 +1,575 @@
+//! Parses JavaScript expressions, so that code from elsewhere can be
+//! evaluated on the examples just like the programs we enumerate.
+//!
+//! We only take the expressions our components can build: int, string and
+//! int array literals, variables, `+`, `-`, `x++`, `[]`, `.length`,
+//! `.push(..)` and `.slice(..)`, with parentheses. Components that work on
+//! both strings and arrays are told apart by the types of the variables.
+//!
+//! Sketches may also have `??` holes, whose types come from where they
+//! are: ints in arithmetic, indices and arguments, and arrays in front of
+//! `.push(..)` and `.slice(..)`.
+
+use crate::ast::Ast;
+use crate::sketch::Sketch;
+use crate::utils::{Any, Type};
+use crate::vocab::Op;
+
+/// Why we couldn't make a program out of some code.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// The code isn't in the subset we parse, or mixes up types.
+    /// `column` counts characters from 1.
+    Syntax { column: usize, message: String },
+    /// `code` has no value on some example, like `s[5]` with a shorter `s`,
+    /// or breaks one of the task's constraints.
+    Eval { code: String },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Syntax { column, message } => write!(f, "{message} at column {column}"),
+            ParseError::Eval { code } => write!(
+                f,
+                "`{code}` can't be evaluated on every example within the task's constraints"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `code` into a program. `var_type` gives the type of each
+/// variable, or `None` for names that aren't variables.
+pub fn parse(code: &str, var_type: &dyn Fn(&str) -> Option<Type>) -> Result<Ast, ParseError> {
+    parse_typed(code, var_type).map(|(ast, _)| ast)
+}
+
+/// Like `parse`, but also gives the type of the program.
+pub(crate) fn parse_typed(
+    code: &str,
+    var_type: &dyn Fn(&str) -> Option<Type>,
+) -> Result<(Ast, Type), ParseError> {
+    let (sketch, ty) = parse_with(code, var_type, false)?;
+    let ast = sketch.to_ast().expect("Holes are only parsed in sketches.");
+    Ok((ast, ty))
+}
+
+/// Like `parse`, but the code may have `??` holes.
+pub(crate) fn parse_sketch(
+    code: &str,
+    var_type: &dyn Fn(&str) -> Option<Type>,
+) -> Result<Sketch, ParseError> {
+    parse_with(code, var_type, true).map(|(sketch, _)| sketch)
+}
+
+fn parse_with(
+    code: &str,
+    var_type: &dyn Fn(&str) -> Option<Type>,
+    holes: bool,
+) -> Result<(Sketch, Type), ParseError> {
+    let tokens = lex(code)?;
+    let mut parser = Parser {
+        code,
+        tokens,
+        next: 0,
+        var_type,
+        holes,
+    };
+
+    let (sketch, ty) = parser.expr()?;
+    let Some(ty) = ty else {
+        return Err(parser.error_at(0, "can't tell what type `??` is here".to_string()));
+    };
+    match parser.peek() {
+        Token::End => Ok((sketch, ty)),
+        token => Err(parser.error(format!("unexpected {}", token.describe()))),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Int(n) => format!("`{n}`"),
+            Token::Str(s) => format!("{s:?}"),
+            Token::Ident(name) => format!("`{name}`"),
+            Token::Punct(punct) => format!("`{punct}`"),
+            Token::End => "end of input".to_string(),
+        }
+    }
+}
+
+/// Longer punctuation first, so `++` isn't read as two `+`s.
+const PUNCTS: [&str; 11] = ["++", "--", "??", "+", "-", "[", "]", "(", ")", ".", ","];
+
+fn column(code: &str, pos: usize) -> usize {
+    code[..pos].chars().count() + 1
+}
+
+/// Splits `code` into tokens, each with the byte offset it starts at.
+fn lex(code: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let error = |pos: usize, message: String| ParseError::Syntax {
+        column: column(code, pos),
+        message,
+    };
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = code[pos..].chars().next() {
+        let rest = &code[pos..];
+        let len = if c.is_whitespace() {
+            c.len_utf8()
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let n = rest[..len]
+                .parse()
+                .map_err(|_| error(pos, format!("`{}` is too big for an int", &rest[..len])))?;
+            tokens.push((pos, Token::Int(n)));
+            len
+        } else if c == '"' {
+            // Find the closing quote, skipping escaped characters.
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in rest.char_indices().skip(1) {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            let end = end.ok_or_else(|| error(pos, "unterminated string".to_string()))?;
+            // JSON string literals are valid JavaScript, and mostly the other way round.
+            let s = serde_json::from_str(&rest[..end])
+                .map_err(|e| error(pos, format!("unsupported string literal ({e})")))?;
+            tokens.push((pos, Token::Str(s)));
+            end
+        } else if is_ident(c) {
+            let len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+            tokens.push((pos, Token::Ident(rest[..len].to_string())));
+            len
+        } else if let Some(punct) = PUNCTS.into_iter().find(|punct| rest.starts_with(punct)) {
+            tokens.push((pos, Token::Punct(punct)));
+            punct.len()
+        } else if c == '\'' || c == '`' {
+            return Err(error(pos, "strings need double quotes".to_string()));
+        } else {
+            return Err(error(pos, format!("unexpected `{c}`")));
+        };
+        pos += len;
+    }
+
+    tokens.push((code.len(), Token::End));
+    Ok(tokens)
+}
+
+/// A part of a program along with its type, which is `None` for a `??`
+/// hole until whatever takes it as an argument decides, see `Parser::typed`.
+type Parsed = (Sketch, Option<Type>);
+
+/// A recursive descent parser, with a function per level of precedence.
+/// Each returns the program it parsed along with its type.
+struct Parser<'a> {
+    code: &'a str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    var_type: &'a dyn Fn(&str) -> Option<Type>,
+    /// Whether we're parsing a sketch, which may have holes.
+    holes: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn peek_at(&self, ahead: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.next + ahead).min(last)].1
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.next].1.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Token::Punct(p) if *p == punct) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "expected `{punct}`, found {}",
+                self.peek().describe()
+            )))
+        }
+    }
+
+    /// An error at the next token.
+    fn error(&self, message: String) -> ParseError {
+        self.error_at(self.next, message)
+    }
+
+    /// An error at the `token`th token.
+    fn error_at(&self, token: usize, message: String) -> ParseError {
+        ParseError::Syntax {
+            column: column(self.code, self.tokens[token].0),
+            message,
+        }
+    }
+
+    /// `arg` as a `ty`, turning a hole into one of that type.
+    /// Returns the type it has instead if it's not one.
+    fn typed(&self, (arg, ty): Parsed, want: Type) -> Result<Sketch, Type> {
+        match ty {
+            None => Ok(Sketch::Hole(want)),
+            Some(ty) if ty == want => Ok(arg),
+            Some(ty) => Err(ty),
+        }
+    }
+
+    /// `a + b - c`, the loosest binding level we have.
+    fn expr(&mut self) -> Result<Parsed, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let at = self.next;
+            let (op, symbol) = if self.eat("+") {
+                (Op::Sum, "+")
+            } else if self.eat("-") {
+                (Op::Sub, "-")
+            } else {
+                return Ok(lhs);
+            };
+
+            let rhs = self.unary()?;
+            let args = [lhs, rhs].map(|arg| self.typed(arg, Type::Int));
+            lhs = match args {
+                [Ok(lhs), Ok(rhs)] => (app(op, vec![lhs, rhs]), Some(Type::Int)),
+                [Err(ty), _] | [_, Err(ty)] => {
+                    return Err(self.error_at(
+                        at,
+                        format!("`{symbol}` only takes ints, not {}", type_name(ty)),
+                    ))
+                }
+            };
+        }
+    }
+
+    /// `-x`, where `-1` is read as a literal rather than the minus of `1`,
+    /// like we print it.
+    fn unary(&mut self) -> Result<Parsed, ParseError> {
+        let at = self.next;
+        if !self.eat("-") {
+            return self.postfix();
+        }
+
+        if let Token::Int(n) = *self.peek() {
+            let postfix = [Token::Punct("["), Token::Punct("."), Token::Punct("++")];
+            if !postfix.contains(self.peek_at(1)) {
+                self.next += 1;
+                return Ok((
+                    Sketch::Literal(Any::Int(self.int(at, -n)?)),
+                    Some(Type::Int),
+                ));
+            }
+        }
+
+        let arg = self.unary()?;
+        let arg = self.typed(arg, Type::Int).map_err(|ty| {
+            self.error_at(at, format!("`-` only takes ints, not {}", type_name(ty)))
+        })?;
+        Ok((app(Op::Minus, vec![arg]), Some(Type::Int)))
+    }
+
+    /// `x++`, which JavaScript only allows once, and only on variables.
+    fn postfix(&mut self) -> Result<Parsed, ParseError> {
+        let (arg, ty) = self.member()?;
+        let at = self.next;
+        if self.eat("--") {
+            return Err(self.error_at(at, "`--` isn't supported".to_string()));
+        }
+        if !self.eat("++") {
+            return Ok((arg, ty));
+        }
+
+        match (&arg, ty) {
+            (Sketch::Var(_), Some(Type::Int)) => Ok((app(Op::Inc, vec![arg]), Some(Type::Int))),
+            (Sketch::Var(_), Some(ty)) => {
+                Err(self.error_at(at, format!("`++` only takes ints, not {}", type_name(ty))))
+            }
+            _ => Err(self.error_at(at, "`++` only takes variables".to_string())),
+        }
+    }
+
+    /// `x[i]`, `x.length`, `x.push(y)` and `x.slice(i)`.
+    fn member(&mut self) -> Result<Parsed, ParseError> {
+        let (mut obj, mut ty) = self.primary()?;
+        loop {
+            let at = self.next;
+            if self.eat("[") {
+                let index = self.int_arg()?;
+                self.expect("]")?;
+                (obj, ty) = match ty {
+                    Some(Type::Str) => (app(Op::StrDeref, vec![obj, index]), Some(Type::Str)),
+                    Some(Type::IntArray) => (app(Op::ArrDeref, vec![obj, index]), Some(Type::Int)),
+                    Some(Type::Int) => {
+                        return Err(self.error_at(at, "can't index into an int".to_string()))
+                    }
+                    None => return Err(self.ambiguous_hole(at)),
+                };
+            } else if self.eat(".") {
+                let name = match self.bump() {
+                    Token::Ident(name) => name,
+                    token => {
+                        return Err(self.error_at(
+                            at + 1,
+                            format!("expected a property, found {}", token.describe()),
+                        ))
+                    }
+                };
+
+                // Only arrays have `push` and `slice`.
+                let obj_ty = match (name.as_str(), ty) {
+                    (_, Some(ty)) => ty,
+                    ("push" | "slice", None) => Type::IntArray,
+                    (_, None) => return Err(self.ambiguous_hole(at)),
+                };
+                let arg = self.typed((obj, ty), obj_ty).expect("Holes take any type.");
+
+                let (member, member_ty) = match (name.as_str(), obj_ty) {
+                    ("length", Type::Str) => (app(Op::StrLen, vec![arg]), Type::Int),
+                    ("length", Type::IntArray) => (app(Op::ArrLen, vec![arg]), Type::Int),
+                    ("push", Type::IntArray) => {
+                        self.expect("(")?;
+                        let elem = self.int_arg()?;
+                        self.expect(")")?;
+                        (app(Op::ArrPush, vec![arg, elem]), Type::Int)
+                    }
+                    ("slice", Type::IntArray) => {
+                        self.expect("(")?;
+                        let start = self.int_arg()?;
+                        self.expect(")")?;
+                        (app(Op::ArrSlice, vec![arg, start]), Type::IntArray)
+                    }
+                    _ => {
+                        return Err(self.error_at(
+                            at + 1,
+                            format!("`.{name}` isn't supported on {}", type_name(obj_ty)),
+                        ))
+                    }
+                };
+                (obj, ty) = (member, Some(member_ty));
+            } else {
+                return Ok((obj, ty));
+            }
+        }
+    }
+
+    /// An argument or index, which all our components take as an int.
+    fn int_arg(&mut self) -> Result<Sketch, ParseError> {
+        let at = self.next;
+        let arg = self.expr()?;
+        self.typed(arg, Type::Int)
+            .map_err(|ty| self.error_at(at, format!("expected an int, not {}", type_name(ty))))
+    }
+
+    /// Literals, variables, holes and parenthesized expressions.
+    fn primary(&mut self) -> Result<Parsed, ParseError> {
+        let at = self.next;
+        match self.bump() {
+            Token::Int(n) => Ok((Sketch::Literal(Any::Int(self.int(at, n)?)), Some(Type::Int))),
+            Token::Str(s) => Ok((Sketch::Literal(Any::Str(s)), Some(Type::Str))),
+            Token::Ident(name) => match (self.var_type)(&name) {
+                Some(ty) => Ok((Sketch::Var(name), Some(ty))),
+                None => Err(self.error_at(at, format!("unknown variable `{name}`"))),
+            },
+            // Whatever takes the hole decides its type.
+            Token::Punct("??") if self.holes => Ok((Sketch::Hole(Type::Int), None)),
+            Token::Punct("??") => {
+                Err(self.error_at(at, "holes are only allowed in sketches".to_string()))
+            }
+            Token::Punct("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => {
+                let mut values = Vec::new();
+                while !self.eat("]") {
+                    if !values.is_empty() {
+                        self.expect(",")?;
+                    }
+                    let at = self.next;
+                    let negative = self.eat("-");
+                    match self.bump() {
+                        Token::Int(n) => values.push(self.int(at, if negative { -n } else { n })?),
+                        _ => {
+                            return Err(
+                                self.error_at(at, "arrays can only hold int literals".to_string())
+                            )
+                        }
+                    }
+                }
+                Ok((Sketch::Literal(Any::IntArray(values)), Some(Type::IntArray)))
+            }
+            token => Err(self.error_at(at, format!("unexpected {}", token.describe()))),
+        }
+    }
+
+    /// An error at the `token`th token, for a hole that could be a string or an array.
+    fn ambiguous_hole(&self, token: usize) -> ParseError {
+        self.error_at(
+            token,
+            "can't tell whether `??` is a string or an array".to_string(),
+        )
+    }
+
+    fn int(&self, at: usize, n: i64) -> Result<i32, ParseError> {
+        n.try_into()
+            .map_err(|_| self.error_at(at, format!("`{n}` is too big for an int")))
+    }
+}
+
+fn app(op: Op, args: Vec<Sketch>) -> Sketch {
+    Sketch::Op { op, args }
+}
+
+fn type_name(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "an int",
+        Type::Str => "a string",
+        Type::IntArray => "an array",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var_type(name: &str) -> Option<Type> {
+        match name {
+            "x" => Some(Type::Int),
+            "s" => Some(Type::Str),
+            "arr" => Some(Type::IntArray),
+            _ => None,
+        }
+    }
+
+    fn sketch_error(code: &str) -> (usize, String) {
+        match parse_sketch(code, &var_type) {
+            Err(ParseError::Syntax { column, message }) => (column, message),
+            result => panic!("expected a syntax error, got {result:?}"),
+        }
+    }
+
+    fn error(code: &str) -> (usize, String) {
+        match parse(code, &var_type) {
+            Err(ParseError::Syntax { column, message }) => (column, message),
+            result => panic!("expected a syntax error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors_where_they_are() {
+        for (code, column, message) in [
+            ("x +", 4, "unexpected end of input"),
+            ("x y", 3, "unexpected `y`"),
+            ("(x + 1", 7, "expected `)`, found end of input"),
+            (r#""é" + @"#, 7, "unexpected `@`"),
+            ("arr.", 5, "expected a property, found end of input"),
+            ("[1, x]", 5, "arrays can only hold int literals"),
+            ("x--", 2, "`--` isn't supported"),
+        ] {
+            assert_eq!(error(code), (column, message.to_string()), "{code}");
+        }
+    }
+
+    #[test]
+    fn reports_type_errors_where_they_are() {
+        for (code, column, message) in [
+            ("x + y", 5, "unknown variable `y`"),
+            ("x - s", 3, "`-` only takes ints, not a string"),
+            ("-arr", 1, "`-` only takes ints, not an array"),
+            ("x[0]", 2, "can't index into an int"),
+            ("s[s]", 3, "expected an int, not a string"),
+            ("arr.foo", 5, "`.foo` isn't supported on an array"),
+            ("1++", 2, "`++` only takes variables"),
+        ] {
+            assert_eq!(error(code), (column, message.to_string()), "{code}");
+        }
+    }
+
+    #[test]
+    fn types_what_it_parses() {
+        for (code, ty) in [
+            ("x - -1", Type::Int),
+            ("s[x]", Type::Str),
+            ("arr.slice(1).length", Type::Int),
+            ("[1, -2]", Type::IntArray),
+        ] {
+            assert_eq!(parse_typed(code, &var_type).unwrap().1, ty, "{code}");
+        }
+    }
+
+    #[test]
+    fn types_holes_by_where_they_are() {
+        let sketch = parse_sketch("s[?? + 1].length - -??", &var_type).unwrap();
+        assert_eq!(sketch.holes(), [Type::Int, Type::Int]);
+        let sketch = parse_sketch("??.slice(??).push(x)", &var_type).unwrap();
+        assert_eq!(sketch.holes(), [Type::IntArray, Type::Int]);
+        assert!(sketch.to_ast().is_none());
+    }
+
+    #[test]
+    fn rejects_holes_of_unknown_type() {
+        assert_eq!(
+            sketch_error("??"),
+            (1, "can't tell what type `??` is here".to_string())
+        );
+        assert_eq!(
+            sketch_error("x + ??.length"),
+            (
+                7,
+                "can't tell whether `??` is a string or an array".to_string()
+            )
+        );
+        assert_eq!(
+            sketch_error("??++"),
+            (3, "`++` only takes variables".to_string())
+        );
+        assert_eq!(
+            parse("s[??]", &var_type),
+            Err(ParseError::Syntax {
+                column: 3,
+                message: "holes are only allowed in sketches".to_string()
+            })
+        );
+    }
+}
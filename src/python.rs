@@ -0,0 +1,358 @@
+//! Python bindings, built with `maturin` (see `pyproject.toml`):
+//!
+//! ```python
+//! import sober
+//!
+//! task = sober.Task({"x": [1, 2], "s": ["a", "bc"]}, output=[2, 4])
+//! synth = sober.Synthesizer(task, vocab=["int", "str"])
+//! for solution in synth.solve(solutions=3, max_level=4, timeout=1.0):
+//!     print(solution.code, solution.values)
+//! print(synth.stop)
+//! ```
+//!
+//! A `Task` takes the same fields as a task file, as keyword arguments,
+//! with plain lists for values. `examples` defaults to the length of the
+//! variables' lists.
+
+// The `#[pymethods]` expansion converts errors into themselves.
+#![allow(clippy::useless_conversion)]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyString, PyTuple};
+use serde_json::{Map, Value};
+
+use crate::loader;
+use crate::synth::{Limits, Stop};
+use crate::utils::*;
+use crate::vocab;
+
+/// A synthesis task. Kept as its task-file JSON, since the synthesizer
+/// takes the task it's given, and we'd like to reuse ours.
+#[pyclass(module = "sober", frozen)]
+#[derive(Clone)]
+struct Task {
+    json: Value,
+}
+
+#[pymethods]
+impl Task {
+    #[new]
+    #[pyo3(signature = (
+        variables,
+        output = None,
+        after = None,
+        *,
+        examples = None,
+        constants = None,
+        read_only = None,
+        must_use = None,
+        must_not_use = None,
+        forbidden_outputs = None,
+        forbidden_after = None,
+    ))]
+    // One per keyword argument.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        variables: &Bound<'_, PyDict>,
+        output: Option<&Bound<'_, PyAny>>,
+        after: Option<&Bound<'_, PyDict>>,
+        examples: Option<usize>,
+        constants: Option<&Bound<'_, PyAny>>,
+        read_only: Option<&Bound<'_, PyAny>>,
+        must_use: Option<&Bound<'_, PyAny>>,
+        must_not_use: Option<&Bound<'_, PyAny>>,
+        forbidden_outputs: Option<&Bound<'_, PyAny>>,
+        forbidden_after: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let variables = to_json(variables.as_any())?;
+        let examples = match examples {
+            Some(examples) => examples,
+            None => variables
+                .as_object()
+                .and_then(|vars| vars.values().next())
+                .map(values_len)
+                .ok_or_else(|| PyValueError::new_err("`examples` is needed without variables"))?,
+        };
+
+        let mut json = Map::new();
+        json.insert("examples".into(), examples.into());
+        json.insert("variables".into(), variables);
+        let fields = [
+            ("output", output),
+            ("after", after.map(|after| after.as_any())),
+            ("constants", constants),
+            ("read_only", read_only),
+            ("must_use", must_use),
+            ("must_not_use", must_not_use),
+            ("forbidden_outputs", forbidden_outputs),
+            ("forbidden_after", forbidden_after),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                json.insert(name.into(), to_json(value)?);
+            }
+        }
+
+        Self::from_value(Value::Object(json))
+    }
+
+    /// Reads a task in the task-file format.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let json = serde_json::from_str(json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Self::from_value(json)
+    }
+
+    fn to_json(&self) -> String {
+        self.json.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Task({})", self.json)
+    }
+}
+
+impl Task {
+    fn from_value(json: Value) -> PyResult<Self> {
+        // Catch mistakes here, rather than when we synthesize.
+        loader::task_from_value(json.clone()).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self { json })
+    }
+}
+
+/// The number of examples a variable's values are for,
+/// either as a plain list or a `{ "type", "values" }` object.
+fn values_len(values: &Value) -> usize {
+    match values {
+        Value::Array(values) => values.len(),
+        Value::Object(typed) => typed
+            .get("values")
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len),
+        _ => 0,
+    }
+}
+
+/// Converts Python values to what the loader expects. The loader then
+/// works out whether lists are `Anies` of ints, strings or int arrays.
+fn to_json(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Ok(b.is_true().into())
+    } else if let Ok(i) = obj.extract::<i64>() {
+        Ok(i.into())
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        Ok(f.value().into())
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Ok(s.to_str()?.into())
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter().map(|item| to_json(&item)).collect()
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple.iter().map(|item| to_json(&item)).collect()
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        dict.iter()
+            .map(|(key, value)| Ok((key.extract::<String>()?, to_json(&value)?)))
+            .collect()
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "can't use a `{}` in a task",
+            obj.get_type().name()?
+        )))
+    }
+}
+
+fn anies_to_py(py: Python<'_>, values: Anies) -> PyObject {
+    match values {
+        Anies::Int(values) => values.into_py(py),
+        Anies::Str(values) => values.into_py(py),
+        Anies::IntArray(values) => values.into_py(py),
+    }
+}
+
+fn condition_to_py(py: Python<'_>, cond: BTreeMap<String, Anies>) -> PyObject {
+    cond.into_iter()
+        .map(|(var, values)| (var, anies_to_py(py, values)))
+        .collect::<BTreeMap<_, _>>()
+        .into_py(py)
+}
+
+fn stop_name(stop: Stop) -> &'static str {
+    match stop {
+        Stop::Found => "found",
+        Stop::Exhausted => "exhausted",
+        Stop::TimedOut => "timed_out",
+        Stop::BankFull => "bank_full",
+        Stop::Cancelled => "cancelled",
+    }
+}
+
+/// A solution, copied out of the synthesizer's bank.
+#[pyclass(module = "sober", frozen, get_all)]
+struct Solution {
+    code: String,
+    #[pyo3(name = "type")]
+    ty: String,
+    level: u8,
+    size: usize,
+    /// What the code evaluates to, one value per example.
+    values: PyObject,
+    /// The values the variables it reads need to have, by name.
+    pre: PyObject,
+    /// The values of the variables it reads or writes afterwards.
+    post: PyObject,
+}
+
+#[pymethods]
+impl Solution {
+    fn __repr__(&self) -> String {
+        format!("Solution({:?})", self.code)
+    }
+}
+
+/// Keeps its bank between calls to `solve`, so asking for more solutions,
+/// or for deeper ones, carries on where the last call stopped.
+#[pyclass(module = "sober", unsendable)]
+struct Synthesizer {
+    synth: crate::synth::Synthesizer,
+    /// Why the last call to `solve` stopped.
+    #[pyo3(get)]
+    stop: Option<&'static str>,
+}
+
+#[pymethods]
+impl Synthesizer {
+    #[new]
+    #[pyo3(signature = (task, vocab = None))]
+    fn new(task: &Task, vocab: Option<Vec<String>>) -> PyResult<Self> {
+        let task = loader::task_from_value(task.json.clone())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let vocab = vocab::select(&vocab.unwrap_or_else(|| vec!["all".to_string()]))
+            .map_err(PyValueError::new_err)?;
+
+        Ok(Self {
+            synth: crate::synth::Synthesizer::new(vocab, task),
+            stop: None,
+        })
+    }
+
+    /// Finds up to `solutions` solutions, best first.
+    /// `timeout` is in seconds.
+    #[pyo3(signature = (solutions = 1, max_level = 10, timeout = None))]
+    fn solve(
+        &mut self,
+        py: Python<'_>,
+        solutions: usize,
+        max_level: u8,
+        timeout: Option<f64>,
+    ) -> PyResult<Vec<Solution>> {
+        let mut limits = Limits::new(max_level.max(1));
+        if let Some(secs) = timeout {
+            let timeout = Duration::try_from_secs_f64(secs)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            limits = limits.with_timeout(timeout);
+        }
+
+        let (found, stop) = self.synth.best(solutions, &limits);
+        self.stop = Some(stop_name(stop));
+
+        Ok(found
+            .into_iter()
+            .map(|snippet| {
+                let snippet = self.synth.get(snippet);
+                Solution {
+                    code: snippet.code(),
+                    ty: format!("{:?}", snippet.ty()),
+                    level: snippet.level(),
+                    size: snippet.size(),
+                    values: anies_to_py(py, snippet.values()),
+                    pre: condition_to_py(py, snippet.pre_condition()),
+                    post: condition_to_py(py, snippet.post_condition()),
+                }
+            })
+            .collect())
+    }
+
+    /// The level we're enumerating.
+    #[getter]
+    fn level(&self) -> u8 {
+        self.synth.level()
+    }
+}
+
+/// Solves `task` in one go. See `Synthesizer.solve`.
+#[pyfunction]
+#[pyo3(signature = (task, solutions = 1, max_level = 10, timeout = None, vocab = None))]
+fn synthesize(
+    py: Python<'_>,
+    task: &Task,
+    solutions: usize,
+    max_level: u8,
+    timeout: Option<f64>,
+    vocab: Option<Vec<String>>,
+) -> PyResult<Vec<Solution>> {
+    Synthesizer::new(task, vocab)?.solve(py, solutions, max_level, timeout)
+}
+
+#[pymodule]
+fn sober(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Task>()?;
+    m.add_class::<Synthesizer>()?;
+    m.add_class::<Solution>()?;
+    m.add_function(wrap_pyfunction!(synthesize, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solving_again_carries_on_with_the_same_bank() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let variables = PyDict::new_bound(py);
+            variables.set_item("x", vec![1, 2]).unwrap();
+            let output = vec![3, 5].into_py(py);
+            let task = Task::new(
+                &variables,
+                Some(output.bind(py)),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            assert_eq!(
+                task.json,
+                serde_json::json!({ "examples": 2, "variables": { "x": [1, 2] }, "output": [3, 5] })
+            );
+
+            let mut synth = Synthesizer::new(&task, None).unwrap();
+            assert!(synth.solve(py, 1, 1, None).unwrap().is_empty());
+            assert_eq!(synth.stop, Some("exhausted"));
+            let shallow = synth.synth.total_bank_size();
+            assert_eq!(synth.level(), 2);
+
+            let found = synth.solve(py, 1, 2, None).unwrap();
+            assert_eq!(synth.stop, Some("found"));
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].code, "x + (x + 1)");
+            assert_eq!(found[0].values.extract::<Vec<i64>>(py).unwrap(), [3, 5]);
+
+            // The bank only grows, ending up where a single deeper call gets to.
+            let mut fresh = Synthesizer::new(&task, None).unwrap();
+            fresh.solve(py, 1, 2, None).unwrap();
+            assert!(synth.synth.total_bank_size() > shallow);
+            assert_eq!(synth.synth.total_bank_size(), fresh.synth.total_bank_size());
+        });
+    }
+}
@@ -0,0 +1,472 @@
+//! An interactive session for building a task a bit at a time.
+//!
+//! The task is kept in the same JSON form the `loader` reads, so anything
+//! a task file can say, `load` can start from. As long as we only change
+//! what the task asks for (its output and after state), we keep the same
+//! `Synthesizer`, and with it everything already in the bank.
+
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::loader;
+use crate::synth::{self, Limits, Stop, Synthesizer};
+use crate::utils::*;
+use crate::vocab;
+
+const HELP: &str = "\
+Commands:
+  var NAME VALUES         define a variable, e.g. `var x [1, 2]`
+  output VALUES           set the expected output, `null` for don't care
+  after NAME VALUES       set the expected value of NAME afterwards
+  example EXAMPLE         add an example, e.g.
+                          `example {\"before\": {\"x\": 3}, \"output\": 4, \"after\": {\"x\": 4}}`
+  load PATH               start over from a task file
+  task                    print the task so far
+  set KEY VALUE           set `vocab`, `max_level`, `timeout` (seconds, or `none`)
+                          or `solutions`
+  run                     look for solutions within the bounds
+  show N                  print candidate N with its pre- and post-conditions
+  show TYPE INDEX         the same, for any program in the bank
+  bank [TYPE] [LEVEL]     list the programs in the bank
+  help                    print this
+  quit                    leave";
+
+pub struct Session {
+    /// The task so far, as the `loader` would read it.
+    task: Map<String, Value>,
+    vocab: Vec<String>,
+    max_level: u8,
+    timeout: Option<Duration>,
+    solutions: usize,
+    /// `None` until the first `run`, and whenever we need a new bank.
+    synth: Option<Synthesizer>,
+    /// The solutions from the last `run`, best first.
+    candidates: Vec<AnyProg>,
+}
+
+impl Session {
+    pub fn new(vocab: Vec<String>, max_level: u8, timeout: Option<Duration>) -> Self {
+        let task = json!({ "examples": 0, "variables": {} });
+        Self {
+            task: task.as_object().cloned().unwrap_or_default(),
+            vocab,
+            max_level,
+            timeout,
+            solutions: 1,
+            synth: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Reads commands from `input` until it runs out or we're told to quit.
+    pub fn run(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "Type `help` for a list of commands.")?;
+
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            let line = line.trim();
+            let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            let result = match command {
+                "" => Ok(()),
+                "quit" | "exit" => return Ok(()),
+                "help" => writeln!(out, "{HELP}").map_err(Into::into),
+                "var" => self.define(rest),
+                "output" => self.expect_output(rest),
+                "after" => self.expect_after(rest),
+                "example" => self.add_example(rest),
+                "load" => self.load(rest),
+                "task" => writeln!(out, "{}", serde_json::to_string_pretty(&self.task)?)
+                    .map_err(Into::into),
+                "set" => self.set(rest),
+                "run" => self.synthesize(out),
+                "show" => self.show(rest, out),
+                "bank" => self.list_bank(rest, out),
+                _ => Err(format!("unknown command `{command}`, try `help`").into()),
+            };
+
+            if let Err(e) = result {
+                writeln!(out, "error: {e}")?;
+            }
+        }
+    }
+
+    fn examples(&self) -> usize {
+        self.task["examples"].as_u64().unwrap_or_default() as usize
+    }
+
+    fn variables(&mut self) -> &mut Map<String, Value> {
+        self.task["variables"]
+            .as_object_mut()
+            .expect("the variables are always an object")
+    }
+
+    /// Changing the variables or examples means the bank has the wrong values.
+    fn invalidate(&mut self) {
+        self.synth = None;
+        self.candidates.clear();
+    }
+
+    fn define(&mut self, args: &str) -> Result<(), Error> {
+        let (name, values) = name_and_json(args)?;
+        let len = values_len(&values)?;
+        if self.variables().is_empty() {
+            self.task.insert("examples".to_string(), len.into());
+        } else if len != self.examples() {
+            return Err(format!("expected {} values, got {len}", self.examples()).into());
+        }
+
+        self.variables().insert(name, values);
+        self.invalidate();
+        Ok(())
+    }
+
+    fn expect_output(&mut self, args: &str) -> Result<(), Error> {
+        let values: Value = serde_json::from_str(args)?;
+        values_len(&values)?;
+        self.task.insert("output".to_string(), values);
+        Ok(())
+    }
+
+    fn expect_after(&mut self, args: &str) -> Result<(), Error> {
+        let (name, values) = name_and_json(args)?;
+        values_len(&values)?;
+        self.task
+            .entry("after")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .ok_or("`after` is not an object")?
+            .insert(name, values);
+        Ok(())
+    }
+
+    /// Adds one example, with a value for every variable,
+    /// and optionally its output and after state.
+    fn add_example(&mut self, args: &str) -> Result<(), Error> {
+        let example: Value = serde_json::from_str(args)?;
+        let before = example["before"]
+            .as_object()
+            .ok_or("an example needs a `before` object")?;
+
+        let names: Vec<String> = self.variables().keys().cloned().collect();
+        if let Some(name) = before.keys().find(|name| !names.contains(name)) {
+            return Err(format!("unknown variable `{name}`, define it with `var` first").into());
+        }
+
+        // Check everything before touching the task, so we don't leave it half-updated.
+        for name in &names {
+            if !before.contains_key(name) {
+                return Err(format!("missing a value for `{name}`").into());
+            }
+            values_mut(&mut self.variables()[name])?;
+        }
+        let after = match &example["after"] {
+            Value::Null => Map::new(),
+            Value::Object(after) => after.clone(),
+            _ => return Err("`after` must be an object".into()),
+        };
+
+        let examples = self.examples();
+        for name in &names {
+            values_mut(&mut self.variables()[name])?.push(before[name].clone());
+        }
+
+        // Examples we didn't say anything about before are "don't care"s.
+        let output = example["output"].clone();
+        if !output.is_null() || self.task.contains_key("output") {
+            let list = self
+                .task
+                .entry("output")
+                .or_insert_with(|| Value::Array(vec![Value::Null; examples]));
+            values_mut(list)?.push(output);
+        }
+
+        if !after.is_empty() || self.task.contains_key("after") {
+            let all_after = self
+                .task
+                .entry("after")
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .ok_or("`after` is not an object")?;
+            for name in after.keys() {
+                all_after
+                    .entry(name.clone())
+                    .or_insert_with(|| Value::Array(vec![Value::Null; examples]));
+            }
+            for (name, list) in all_after.iter_mut() {
+                let value = after.get(name).cloned().unwrap_or_default();
+                values_mut(list)?.push(value);
+            }
+        }
+
+        self.task
+            .insert("examples".to_string(), (examples + 1).into());
+        self.invalidate();
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), Error> {
+        let json = std::fs::read_to_string(path)?;
+        let task: Value = serde_json::from_str(&json)?;
+        // Check it now, rather than on the next `run`.
+        loader::task_from_value(task.clone())?;
+
+        self.task = task.as_object().cloned().unwrap_or_default();
+        self.invalidate();
+        Ok(())
+    }
+
+    fn set(&mut self, args: &str) -> Result<(), Error> {
+        let (key, value) = args.split_once(' ').ok_or("usage: set KEY VALUE")?;
+        let value = value.trim();
+
+        match key {
+            "vocab" => {
+                let vocab: Vec<String> = value.split(',').map(|s| s.trim().to_string()).collect();
+                vocab::select(&vocab)?;
+                self.vocab = vocab;
+                self.invalidate();
+            }
+            "max_level" => match value.parse()? {
+                level @ 1..=255 => self.max_level = level,
+                _ => return Err("max_level must be between 1 and 255".into()),
+            },
+            "timeout" => {
+                self.timeout = match value {
+                    "none" => None,
+                    secs => Some(Duration::try_from_secs_f64(secs.parse()?)?),
+                }
+            }
+            "solutions" => self.solutions = value.parse()?,
+            _ => return Err(format!("unknown setting `{key}`").into()),
+        }
+
+        Ok(())
+    }
+
+    /// Shows the best solutions we know of, enumerating further if we
+    /// don't know of enough yet.
+    fn synthesize(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        let task = loader::task_from_value(Value::Object(self.task.clone()))?;
+
+        let synth = match self.synth.take() {
+            Some(mut synth) => match synth.retarget(task) {
+                Ok(()) => synth,
+                Err(task) => Synthesizer::new(vocab::select(&self.vocab)?, *task),
+            },
+            None => Synthesizer::new(vocab::select(&self.vocab)?, task),
+        };
+        let synth = self.synth.insert(synth);
+
+        let mut limits = Limits::new(self.max_level);
+        if let Some(timeout) = self.timeout {
+            limits = limits.with_timeout(timeout);
+        }
+
+        let (found, stop) = synth.best_solutions(self.solutions, &limits, &synth::default_ranking);
+        let store = synth.store();
+
+        let why = match stop {
+            Stop::Found => "found enough solutions",
+            Stop::Exhausted => "searched every level",
+            Stop::TimedOut => "timed out",
+            Stop::BankFull => "filled the bank",
+            Stop::Cancelled => "cancelled",
+        };
+        writeln!(
+            out,
+            "{why} (level {}, {} programs in the bank)",
+            synth.level().min(limits.max_level()),
+            Type::ALL.iter().map(|ty| store.count(*ty)).sum::<usize>(),
+        )?;
+        for (i, prog) in found.iter().enumerate() {
+            writeln!(out, "  [{i}] {}", prog.code(store))?;
+        }
+
+        self.candidates = found;
+        Ok(())
+    }
+
+    fn show(&self, args: &str, out: &mut dyn Write) -> Result<(), Error> {
+        let synth = self
+            .synth
+            .as_ref()
+            .ok_or("nothing to show yet, try `run`")?;
+        let store = synth.store();
+
+        let prog = match args.split_once(' ') {
+            Some((ty, idx)) => {
+                let ty = parse_type(ty)?;
+                let idx: usize = idx.trim().parse()?;
+                if idx >= store.count(ty) {
+                    return Err(format!(
+                        "there are only {} programs of that type",
+                        store.count(ty)
+                    )
+                    .into());
+                }
+                AnyProg::from_index(ty, idx)
+            }
+            None => {
+                let i: usize = args.parse()?;
+                *self
+                    .candidates
+                    .get(i)
+                    .ok_or_else(|| format!("there are only {} candidates", self.candidates.len()))?
+            }
+        };
+
+        let (pre, post) = prog.conditions(store);
+        pre.pretty_print(out, store)?;
+        writeln!(out, "\n{}", prog.code(store))?;
+        post.pretty_print(out, store)?;
+        writeln!(
+            out,
+            "\nvalues: {}",
+            serde_json::to_string(&prog.values_idx(store).to_anies(store))?
+        )?;
+        Ok(())
+    }
+
+    fn list_bank(&self, args: &str, out: &mut dyn Write) -> Result<(), Error> {
+        let synth = self.synth.as_ref().ok_or("the bank is empty, try `run`")?;
+        let store = synth.store();
+
+        let mut args = args.split_whitespace();
+        let types = match args.next() {
+            Some(ty) => vec![parse_type(ty)?],
+            None => Type::ALL.to_vec(),
+        };
+        let level = args.next().map(str::parse::<u8>).transpose()?;
+
+        for ty in types {
+            writeln!(out, "{ty:?}:")?;
+            for idx in 0..store.count(ty) {
+                let prog = AnyProg::from_index(ty, idx);
+                let prog_level = u8::from(prog.level(store));
+                if level.is_some_and(|level| level != prog_level) {
+                    continue;
+                }
+                writeln!(out, "  {idx:>5}  level {prog_level}  {}", prog.code(store))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits `NAME JSON`.
+fn name_and_json(args: &str) -> Result<(String, Value), Error> {
+    let (name, json) = args.split_once(' ').ok_or("expected a name and a value")?;
+    Ok((name.to_string(), serde_json::from_str(json)?))
+}
+
+/// The number of examples in a list of values, plain or typed.
+fn values_len(values: &Value) -> Result<usize, Error> {
+    let list = match values {
+        Value::Object(typed) => typed.get("values").ok_or("expected a `values` list")?,
+        list => list,
+    };
+    list.as_array()
+        .map(Vec::len)
+        .ok_or_else(|| "expected a list of values, one per example".into())
+}
+
+/// The list inside a list of values, plain or typed.
+fn values_mut(values: &mut Value) -> Result<&mut Vec<Value>, Error> {
+    let list = match values {
+        Value::Object(typed) => typed.get_mut("values").ok_or("expected a `values` list")?,
+        list => list,
+    };
+    list.as_array_mut()
+        .ok_or_else(|| "expected a list of values, one per example".into())
+}
+
+fn parse_type(name: &str) -> Result<Type, Error> {
+    match name.to_lowercase().as_str() {
+        "int" => Ok(Type::Int),
+        "str" => Ok(Type::Str),
+        "int_array" | "intarray" | "array" => Ok(Type::IntArray),
+        _ => Err(format!("unknown type `{name}`, expected int, str or int_array").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session::new(vec!["all".to_string()], 3, None)
+    }
+
+    /// Runs the commands in `script`, returning what they print,
+    /// one line per command, without the greeting and prompts.
+    fn run(session: &mut Session, script: &str) -> String {
+        let mut out = Vec::new();
+        session.run(&mut script.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        out.strip_prefix("Type `help` for a list of commands.\n")
+            .unwrap()
+            .replace("> ", "")
+    }
+
+    #[test]
+    fn keeps_the_bank_while_only_the_spec_changes() {
+        let mut session = session();
+        assert_eq!(
+            run(
+                &mut session,
+                "var x [1, 2]\noutput [2, 3]\nafter x [1, 2]\nrun\noutput [3, 4]\nrun\n"
+            ),
+            "found enough solutions (level 2, 26 programs in the bank)\n  [0] x + 1\n\
+             found enough solutions (level 3, 89 programs in the bank)\n  [0] x + (1 + 1)\n"
+        );
+    }
+
+    #[test]
+    fn adds_examples_to_every_list() {
+        let mut session = session();
+        run(
+            &mut session,
+            "var x [1, 2]\nvar s [\"a\", \"b\"]\nafter x [1, 2]\n\
+             example {\"before\": {\"x\": 5, \"s\": \"c\"}, \"output\": 6}\n",
+        );
+        assert_eq!(
+            Value::Object(session.task),
+            json!({
+                "examples": 3,
+                "variables": { "x": [1, 2, 5], "s": ["a", "b", "c"] },
+                "output": [null, null, 6],
+                "after": { "x": [1, 2, null] },
+            })
+        );
+    }
+
+    #[test]
+    fn reports_bad_commands_and_carries_on() {
+        let mut session = session();
+        assert_eq!(
+            run(
+                &mut session,
+                "var x [1, 2]\noutput {\"type\": \"Int\"}\nvar y [1]\n\
+                 example {\"before\": {}}\nshow 0\nfrobnicate\noutput [1, 2]\n"
+            ),
+            "error: expected a `values` list\n\
+             error: expected 2 values, got 1\n\
+             error: missing a value for `x`\n\
+             error: nothing to show yet, try `run`\n\
+             error: unknown command `frobnicate`, try `help`\n"
+        );
+        assert_eq!(session.task["output"], json!([1, 2]));
+    }
+}
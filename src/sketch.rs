@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::ast::Ast;
+use crate::synth::{Limits, Snippet, Stop, Synthesizer};
+use crate::utils::*;
+use crate::vocab::Op;
+
+/// A partial program, e.g. `s[?? + 1]` or `arr.slice(??)`,
+/// where the holes are filled in with programs from the bank.
+/// See `Synthesizer::sketch`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Sketch {
+    /// A hole, to be filled with any program of the given type.
+    Hole(Type),
+    Var(String),
+    Literal(Any),
+    /// A component applied to the sub-sketches, in order.
+    Op {
+        op: Op,
+        args: Vec<Sketch>,
+    },
+}
+
+impl Sketch {
+    /// The types of the holes, in the order they are filled.
+    pub fn holes(&self) -> Vec<Type> {
+        let mut holes = Vec::new();
+        self.collect_holes(&mut holes);
+        holes
+    }
+
+    fn collect_holes(&self, holes: &mut Vec<Type>) {
+        match self {
+            Sketch::Hole(ty) => holes.push(*ty),
+            Sketch::Var(_) | Sketch::Literal(_) => (),
+            Sketch::Op { args, .. } => args.iter().for_each(|arg| arg.collect_holes(holes)),
+        }
+    }
+
+    /// The program, if the sketch has no holes.
+    pub fn to_ast(&self) -> Option<Ast> {
+        self.fill(&mut std::iter::empty())
+    }
+
+    /// The program with the holes filled by `fillers`, in order,
+    /// or `None` if there are too few of them.
+    pub fn fill(&self, fillers: &mut impl Iterator<Item = Ast>) -> Option<Ast> {
+        Some(match self {
+            Sketch::Hole(_) => fillers.next()?,
+            Sketch::Var(name) => Ast::Var(name.clone()),
+            Sketch::Literal(value) => Ast::Literal(value.clone()),
+            Sketch::Op { op, args } => Ast::Op {
+                op: *op,
+                args: args.iter().map(|arg| arg.fill(fillers)).try_collect()?,
+            },
+        })
+    }
+}
+
+/// A filling of a sketch that satisfies the task.
+pub struct Filling {
+    /// The filled sketch, as evaluated into the bank.
+    /// Note that due to observational equivalence, this may be
+    /// a different but equivalent program.
+    pub snippet: Snippet,
+    /// The code of the sketch with its holes filled.
+    pub code: String,
+}
+
+/// Searches for hole fillings that make a sketch satisfy the task.
+///
+/// The bank is grown by the synthesizer as usual, so fillings are tried
+/// roughly in order of level. Every time a program enters the bank, we try
+/// it in each hole of its type, with every older program in the other holes.
+/// This way, each filling is tried exactly once.
+pub(crate) struct SketchSearch {
+    sketch: Sketch,
+    holes: Vec<Type>,
+    /// How many programs of each type we've already tried as the newest filler.
+    done: HashMap<Type, usize>,
+    /// Sketches without holes only need to be tried once.
+    tried: bool,
+    found: HashSet<AnyProg>,
+    pending: VecDeque<Filling>,
+}
+
+impl SketchSearch {
+    pub fn new(sketch: Sketch) -> Self {
+        let holes = sketch.holes();
+        Self {
+            sketch,
+            holes,
+            done: Type::ALL.into_iter().map(|ty| (ty, 0)).collect(),
+            tried: false,
+            found: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Keeps filling holes and growing the bank until we find a filled
+    /// sketch that satisfies the task, or we're past `limits`.
+    pub fn next_solution(
+        &mut self,
+        synth: &mut Synthesizer,
+        limits: &Limits,
+    ) -> Result<Filling, Stop> {
+        loop {
+            if let Some(filling) = self.pending.pop_front() {
+                return Ok(filling);
+            }
+
+            if self.holes.is_empty() && self.tried {
+                // Growing the bank won't change anything.
+                return Err(Stop::Exhausted);
+            }
+
+            if !self.step(synth) {
+                synth.next_within(limits)?;
+            }
+        }
+    }
+
+    /// Tries the oldest untried program in the bank as a filler.
+    /// Returns false if there was nothing left to try.
+    fn step(&mut self, synth: &mut Synthesizer) -> bool {
+        if self.holes.is_empty() {
+            self.tried = true;
+            self.try_filling(&[], synth);
+            return true;
+        }
+
+        // Pick the lowest level program among the untried ones.
+        // Filling the sketch adds programs to the bank too, so we only take
+        // programs from levels the synthesizer is done with. Otherwise,
+        // we'd keep filling the sketch with itself.
+        let store = synth.store();
+        let newest = Type::ALL
+            .into_iter()
+            .filter(|ty| self.done[ty] < store.count(*ty))
+            .map(|ty| AnyProg::from_index(ty, self.done[&ty]))
+            .filter(|prog| u8::from(prog.level(store)) < synth.level())
+            .min_by_key(|prog| prog.level(store));
+
+        let Some(newest) = newest else {
+            return false;
+        };
+
+        let mut fillers = Vec::with_capacity(self.holes.len());
+        for hole in 0..self.holes.len() {
+            if self.holes[hole] == newest.ty() {
+                self.fill_holes(0, hole, newest, &mut fillers, synth);
+            }
+        }
+
+        *self.done.get_mut(&newest.ty()).unwrap() += 1;
+        true
+    }
+
+    /// Recursively fills the holes from `curr` onwards, putting `newest` in
+    /// `hole` and only older programs in the rest.
+    fn fill_holes(
+        &mut self,
+        curr: usize,
+        hole: usize,
+        newest: AnyProg,
+        fillers: &mut Vec<AnyProg>,
+        synth: &mut Synthesizer,
+    ) {
+        if curr == self.holes.len() {
+            self.try_filling(fillers, synth);
+            return;
+        }
+
+        if curr == hole {
+            fillers.push(newest);
+            self.fill_holes(curr + 1, hole, newest, fillers, synth);
+            fillers.pop();
+            return;
+        }
+
+        // Holes of the same type before `hole` only take strictly older
+        // programs, so fillings using `newest` twice are only tried once.
+        let ty = self.holes[curr];
+        let end = if ty != newest.ty() {
+            self.done[&ty]
+        } else if curr < hole {
+            newest.index()
+        } else {
+            newest.index() + 1
+        };
+
+        for idx in 0..end {
+            fillers.push(AnyProg::from_index(ty, idx));
+            self.fill_holes(curr + 1, hole, newest, fillers, synth);
+            fillers.pop();
+        }
+    }
+
+    fn try_filling(&mut self, fillers: &[AnyProg], synth: &mut Synthesizer) {
+        let Some((prog, code)) = synth.fill(&self.sketch, fillers) else {
+            return;
+        };
+
+        if synth.task().is_solution(&prog, synth.store()) && self.found.insert(prog) {
+            self.pending.push_back(Filling {
+                snippet: synth.snippet(prog),
+                code,
+            });
+        }
+    }
+}
+
+/// Fills a sketch within some limits, see `Synthesizer::fill_sketch`.
+pub struct Fillings<'s> {
+    synth: &'s mut Synthesizer,
+    search: SketchSearch,
+    limits: Limits,
+    stop: Option<Stop>,
+}
+
+impl<'s> Fillings<'s> {
+    pub(crate) fn new(synth: &'s mut Synthesizer, sketch: Sketch, limits: Limits) -> Self {
+        Self {
+            synth,
+            search: SketchSearch::new(sketch),
+            limits,
+            stop: None,
+        }
+    }
+
+    /// Why we stopped, once we have.
+    pub fn stop(&self) -> Option<Stop> {
+        self.stop
+    }
+
+    pub fn synth(&self) -> &Synthesizer {
+        self.synth
+    }
+}
+
+impl Iterator for Fillings<'_> {
+    type Item = Filling;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stop.is_some() {
+            return None;
+        }
+
+        match self.search.next_solution(self.synth, &self.limits) {
+            Ok(filling) => Some(filling),
+            Err(stop) => {
+                self.stop = Some(stop);
+                None
+            }
+        }
+    }
+}
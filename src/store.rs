@@ -1,12 +1,25 @@
 use std::collections::HashMap;
 use std::ops::Index;
 
+use smallvec::{smallvec, SmallVec};
+
 use crate::utils::*;
 use crate::*;
 
-use self::task::VarMap;
+use self::task::{Component, VarMap};
+
+/// A bitmask over the constrained components in the `Bank`,
+/// recording which ones a program uses.
+pub type Uses = u64;
 
-type OEKey<T> = (Vec<T>, Option<Pointer>, PreCondition, PostCondition);
+/// How many must-use and must-not-use components a task can have,
+/// one per bit of `Uses`.
+pub(crate) const MAX_CONSTRAINTS: usize = Uses::BITS as usize;
+
+/// Programs are only equivalent if they also use the same required
+/// components, or a program that adds one could be dropped for an
+/// equivalent that doesn't. Forbidden ones never make it into the store.
+type OEKey<T> = (Vec<T>, Option<Pointer>, PreCondition, PostCondition, Uses);
 type VarKey<T> = (String, Vec<T>);
 
 #[derive(Debug)]
@@ -39,6 +52,8 @@ impl MaxIdx<IntArray> for MaxPIdx {
 
 pub trait Store<T: Value> {
     fn get_values(&self, idx: VIdx<T>) -> &[T];
+    // Boxed, to match `Index`.
+    #[allow(clippy::borrowed_box)]
     fn get_program(&self, idx: PIdx<T>) -> &Box<dyn Program<T>>;
 
     /// Tries to add the MaybeProgram to the store.
@@ -86,6 +101,13 @@ where
 pub struct Bank {
     examples: usize,
     var_map: VarMap,
+    /// Variables that no program in the store may mutate.
+    read_only: SmallVec<[bool; 4]>,
+    /// Components with a must-use or must-not-use constraint.
+    /// Bit `i` of a program's `Uses` is set iff it uses `components[i]`.
+    components: Vec<Component>,
+    required: Uses,
+    forbidden: Uses,
 
     // Integers
     int_vals: Vec<Int>,
@@ -111,6 +133,10 @@ impl Bank {
         // TODO allocate larger chunks here?
         Self {
             examples,
+            read_only: smallvec![false; var_map.len()],
+            components: Vec::new(),
+            required: 0,
+            forbidden: 0,
             var_map,
             int_vals: Vec::new(),
             ints: Vec::new(),
@@ -135,6 +161,15 @@ impl Bank {
         }
     }
 
+    /// The number of programs of type `ty` in the store.
+    pub fn count(&self, ty: Type) -> usize {
+        match ty {
+            Type::Int => self.ints.len(),
+            Type::Str => self.strs.len(),
+            Type::IntArray => self.int_arrs.len(),
+        }
+    }
+
     pub fn var_map(&self) -> &VarMap {
         &self.var_map
     }
@@ -142,6 +177,60 @@ impl Bank {
     pub fn variables(&self) -> usize {
         self.var_map.len()
     }
+
+    pub fn set_read_only(&mut self, var: Pointer) {
+        self.read_only[var] = true;
+    }
+
+    pub fn is_read_only(&self, var: Pointer) -> bool {
+        self.read_only[var]
+    }
+
+    /// Adds a constraint that programs must (`required`) or must not use
+    /// `component`. Constraints must be added before any programs.
+    pub fn constrain(&mut self, component: Component, required: bool) {
+        debug_assert!(self.ints.is_empty() && self.strs.is_empty() && self.int_arrs.is_empty());
+        assert!(
+            self.components.len() < MAX_CONSTRAINTS,
+            "A task can have at most {MAX_CONSTRAINTS} must-use and must-not-use components."
+        );
+
+        let bit = 1 << self.components.len();
+        self.components.push(component);
+        if required {
+            self.required |= bit;
+        } else {
+            self.forbidden |= bit;
+        }
+    }
+
+    /// The constraint bits a program consisting of only `component` sets.
+    pub fn uses(&self, component: &Component) -> Uses {
+        self.components
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| *c == component)
+            .fold(0, |uses, (i, _)| uses | 1 << i)
+    }
+
+    /// Checks that a program using `uses` uses no forbidden component.
+    pub fn allows(&self, uses: Uses) -> bool {
+        uses & self.forbidden == 0
+    }
+
+    /// Checks that a program using `uses` uses every required component.
+    pub fn satisfies(&self, uses: Uses) -> bool {
+        uses & self.required == self.required
+    }
+
+    /// Checks that a program with these conditions leaves
+    /// every read-only variable as it found it.
+    pub fn preserves_frame(&self, pre: &PreCondition, post: &PostCondition) -> bool {
+        self.read_only
+            .iter()
+            .enumerate()
+            .all(|(var, read_only)| !read_only || pre.get(var) == post.get(var))
+    }
 }
 
 impl Store<Int> for Bank {
@@ -166,7 +255,8 @@ impl Store<Int> for Bank {
         let pre = program.pre_condition().clone();
         let post = program.post_condition().clone();
 
-        let oe_key: OEKey<Int> = (values, program.pointer(), pre, post);
+        let uses = program.uses() & self.required;
+        let oe_key: OEKey<Int> = (values, program.pointer(), pre, post, uses);
 
         if let Some(idx) = self.int_oe.get(&oe_key) {
             return Err(*idx);
@@ -207,25 +297,35 @@ impl Store<Int> for Bank {
             let val_idx = self.int_vals.len().into();
             let prog_idx = self.ints.len().into();
             self.int_vals.extend_from_slice(&key.1);
+            let uses = self.uses(&Component::Var(key.0.clone()));
             let val_program =
-                Variable::<Int>::new(key.0.clone(), val_idx, pointer, self.variables());
+                Variable::<Int>::new(key.0.clone(), val_idx, pointer, self.variables(), uses);
             let (pre, post) = val_program.conditions();
             let (pre, post) = (pre.clone(), post.clone());
             self.ints.push(val_program);
 
             // Each variable is provably unique
-            let oe_key: OEKey<Int> = (key.1, Some(pointer), pre, post);
+            let oe_key: OEKey<Int> = (
+                key.1.clone(),
+                Some(pointer),
+                pre,
+                post,
+                uses & self.required,
+            );
             debug_assert!(!self.int_oe.contains_key(&oe_key));
             self.int_oe.insert(oe_key, prog_idx);
+            self.int_vars.insert(key, prog_idx);
 
             Ok(prog_idx)
         }
     }
 
     fn put_constant(&mut self, code: &str, value: Int) -> Result<PIdx<Int>, PIdx<Int>> {
+        let constant = Any::Int(value);
         let values = vec![value; self.examples];
         let empty = Condition::empty(self.variables());
-        let oe_key: OEKey<Int> = (values, None, empty.clone(), empty);
+        let uses = self.uses(&Component::Const(code.to_string()));
+        let oe_key: OEKey<Int> = (values, None, empty.clone(), empty, uses & self.required);
 
         if let Some(idx) = self.int_oe.get(&oe_key) {
             return Err(*idx);
@@ -234,7 +334,7 @@ impl Store<Int> for Bank {
         let val_idx = self.int_vals.len().into();
         let prog_idx = self.ints.len().into();
         self.int_vals.extend_from_slice(&oe_key.0);
-        let program = Constant::new(code.to_string(), val_idx, self.variables());
+        let program = Constant::new(code.to_string(), constant, val_idx, self.variables(), uses);
         self.ints.push(program);
 
         Ok(prog_idx)
@@ -263,7 +363,8 @@ impl Store<Str> for Bank {
         let pre = program.pre_condition().clone();
         let post = program.post_condition().clone();
 
-        let oe_key: OEKey<Str> = (values, program.pointer(), pre, post);
+        let uses = program.uses() & self.required;
+        let oe_key: OEKey<Str> = (values, program.pointer(), pre, post, uses);
 
         if let Some(idx) = self.str_oe.get(&oe_key) {
             return Err(*idx);
@@ -304,25 +405,35 @@ impl Store<Str> for Bank {
             let val_idx = self.str_vals.len().into();
             let prog_idx = self.strs.len().into();
             self.str_vals.extend_from_slice(&key.1);
+            let uses = self.uses(&Component::Var(key.0.clone()));
             let val_program =
-                Variable::<Str>::new(key.0.clone(), val_idx, pointer, self.variables());
+                Variable::<Str>::new(key.0.clone(), val_idx, pointer, self.variables(), uses);
             let (pre, post) = val_program.conditions();
             let (pre, post) = (pre.clone(), post.clone());
             self.strs.push(val_program);
 
             // Each variable is provably unique
-            let oe_key: OEKey<Str> = (key.1, Some(pointer), pre, post);
+            let oe_key: OEKey<Str> = (
+                key.1.clone(),
+                Some(pointer),
+                pre,
+                post,
+                uses & self.required,
+            );
             debug_assert!(!self.str_oe.contains_key(&oe_key));
             self.str_oe.insert(oe_key, prog_idx);
+            self.str_vars.insert(key, prog_idx);
 
             Ok(prog_idx)
         }
     }
 
     fn put_constant(&mut self, code: &str, value: Str) -> Result<PIdx<Str>, PIdx<Str>> {
+        let constant = Any::Str(value.clone());
         let values = vec![value; self.examples];
         let empty = Condition::empty(self.variables());
-        let oe_key: OEKey<Str> = (values, None, empty.clone(), empty);
+        let uses = self.uses(&Component::Const(code.to_string()));
+        let oe_key: OEKey<Str> = (values, None, empty.clone(), empty, uses & self.required);
 
         if let Some(idx) = self.str_oe.get(&oe_key) {
             return Err(*idx);
@@ -331,7 +442,7 @@ impl Store<Str> for Bank {
         let val_idx = self.str_vals.len().into();
         let prog_idx = self.strs.len().into();
         self.str_vals.extend_from_slice(&oe_key.0);
-        let program = Constant::new(code.to_string(), val_idx, self.variables());
+        let program = Constant::new(code.to_string(), constant, val_idx, self.variables(), uses);
         self.strs.push(program);
 
         Ok(prog_idx)
@@ -359,7 +470,8 @@ impl Store<IntArray> for Bank {
         let pre = program.pre_condition().clone();
         let post = program.post_condition().clone();
 
-        let oe_key: OEKey<IntArray> = (values, program.pointer(), pre, post);
+        let uses = program.uses() & self.required;
+        let oe_key: OEKey<IntArray> = (values, program.pointer(), pre, post, uses);
 
         if let Some(idx) = self.int_arr_oe.get(&oe_key) {
             return Err(*idx);
@@ -400,16 +512,24 @@ impl Store<IntArray> for Bank {
             let val_idx = self.int_arr_vals.len().into();
             let prog_idx = self.int_arrs.len().into();
             self.int_arr_vals.extend_from_slice(&key.1);
+            let uses = self.uses(&Component::Var(key.0.clone()));
             let val_program =
-                Variable::<IntArray>::new(key.0.clone(), val_idx, pointer, self.variables());
+                Variable::<IntArray>::new(key.0.clone(), val_idx, pointer, self.variables(), uses);
             let (pre, post) = val_program.conditions();
             let (pre, post) = (pre.clone(), post.clone());
             self.int_arrs.push(val_program);
 
             // Each variable is provably unique
-            let oe_key: OEKey<IntArray> = (key.1, Some(pointer), pre, post);
+            let oe_key: OEKey<IntArray> = (
+                key.1.clone(),
+                Some(pointer),
+                pre,
+                post,
+                uses & self.required,
+            );
             debug_assert!(!self.int_arr_oe.contains_key(&oe_key));
             self.int_arr_oe.insert(oe_key, prog_idx);
+            self.int_arr_vars.insert(key, prog_idx);
 
             Ok(prog_idx)
         }
@@ -420,9 +540,11 @@ impl Store<IntArray> for Bank {
         code: &str,
         value: IntArray,
     ) -> Result<PIdx<IntArray>, PIdx<IntArray>> {
+        let constant = Any::IntArray(value.clone());
         let values = vec![value; self.examples];
         let empty = Condition::empty(self.variables());
-        let oe_key: OEKey<IntArray> = (values, None, empty.clone(), empty);
+        let uses = self.uses(&Component::Const(code.to_string()));
+        let oe_key: OEKey<IntArray> = (values, None, empty.clone(), empty, uses & self.required);
 
         if let Some(idx) = self.int_arr_oe.get(&oe_key) {
             return Err(*idx);
@@ -431,9 +553,37 @@ impl Store<IntArray> for Bank {
         let val_idx = self.int_arr_vals.len().into();
         let prog_idx = self.int_arrs.len().into();
         self.int_arr_vals.extend_from_slice(&oe_key.0);
-        let program = Constant::new(code.to_string(), val_idx, self.variables());
+        let program = Constant::new(code.to_string(), constant, val_idx, self.variables(), uses);
         self.int_arrs.push(program);
 
         Ok(prog_idx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puts_each_variable_once() {
+        let vars = ["x", "s", "arr"].map(String::from).into_iter().collect();
+        let mut store = Bank::new(2, vars);
+
+        let x = store.put_variable("x".to_string(), vec![1, 2], 0);
+        assert!(x.is_ok());
+        assert!(store.put_variable("x".to_string(), vec![1, 2], 0) == Err(x.unwrap()));
+        // The same variable after a mutation is another program.
+        assert!(store.put_variable("x".to_string(), vec![2, 3], 0).is_ok());
+        assert_eq!(store.count(Type::Int), 2);
+
+        let s = vec!["a".to_string(), "b".to_string()];
+        let first = store.put_variable("s".to_string(), s.clone(), 1);
+        assert!(store.put_variable("s".to_string(), s, 1) == Err(first.unwrap()));
+        assert_eq!(store.count(Type::Str), 1);
+
+        let arr = vec![vec![1], vec![]];
+        let first = store.put_variable("arr".to_string(), arr.clone(), 2);
+        assert!(store.put_variable("arr".to_string(), arr, 2) == Err(first.unwrap()));
+        assert_eq!(store.count(Type::IntArray), 1);
+    }
+}
@@ -1,20 +1,300 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
 use std::ops::FromResidual;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
+
+use crate::ast::Ast;
+use crate::cond::{Condition, Pointer};
+use crate::lang::{Lang, Unsupported};
 use crate::ops::*;
+use crate::parse::{self, ParseError};
+use crate::sketch::{Fillings, Sketch};
 use crate::store::*;
-use crate::task::SynthesisTask;
+use crate::task::{AfterState, Mismatch, SynthesisTask};
 use crate::utils::*;
-use crate::vocab;
-use crate::vocab::ConstVal;
 use crate::vocab::Vocab;
+use crate::vocab::{ConstVal, Op};
+
+/// Orders two solutions so that the better one sorts first.
+/// See `Synthesizer::best_by`.
+pub type Ranking = dyn Fn(&SnippetRef, &SnippetRef) -> Ordering;
+
+/// Prefers lower levels, then smaller programs, then preferred components
+/// at the root (see `Op`).
+pub fn default_ranking(lhs: &SnippetRef, rhs: &SnippetRef) -> Ordering {
+    lhs.level()
+        .cmp(&rhs.level())
+        .then_with(|| lhs.size().cmp(&rhs.size()))
+        .then_with(|| lhs.op().cmp(&rhs.op()))
+}
+
+/// Bounds on how far we keep searching.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// The last level we enumerate.
+    pub(crate) max_level: Level,
+    pub(crate) deadline: Option<Instant>,
+    /// The most programs we keep in the bank, of all types together.
+    pub(crate) max_bank_size: Option<usize>,
+    /// Lets another thread stop the search early.
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Limits {
+    pub fn new(max_level: u8) -> Self {
+        Self {
+            max_level: max_level.into(),
+            deadline: None,
+            max_bank_size: None,
+            cancel: None,
+        }
+    }
+
+    pub fn max_level(&self) -> u8 {
+        self.max_level.into()
+    }
+
+    /// Stops the search once `cancel` is set.
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Stops once the bank holds `size` programs, counting the variables
+    /// and constants. We stop between programs, so it may hold a few more.
+    pub fn with_max_bank_size(mut self, size: usize) -> Self {
+        self.max_bank_size = Some(size);
+        self
+    }
+}
+
+impl Default for Limits {
+    /// Every level there is, without any other bounds.
+    fn default() -> Self {
+        Self::new(u8::MAX)
+    }
+}
+
+/// Why a search stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stop {
+    /// We found all the solutions we were asked for.
+    Found,
+    /// We enumerated every level within the limits,
+    /// or a whole level didn't add anything to the bank.
+    Exhausted,
+    TimedOut,
+    /// The bank grew to the maximum size.
+    BankFull,
+    Cancelled,
+}
+
+/// Tells apart the programs of different synthesizers.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A program a `Synthesizer` enumerated. Look at it with `Synthesizer::get`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Snippet {
+    prog: AnyProg,
+    /// The id of the synthesizer whose bank `prog` is in.
+    owner: usize,
+}
+
+/// A `Snippet` along with the synthesizer it came from.
+#[derive(Clone, Copy)]
+pub struct SnippetRef<'s> {
+    prog: AnyProg,
+    synth: &'s Synthesizer,
+}
+
+impl<'s> SnippetRef<'s> {
+    pub fn snippet(&self) -> Snippet {
+        self.synth.snippet(self.prog)
+    }
+
+    pub fn code(&self) -> String {
+        self.prog.code(&self.synth.store)
+    }
+
+    pub fn ty(&self) -> Type {
+        self.prog.ty()
+    }
+
+    pub fn level(&self) -> u8 {
+        self.prog.level(&self.synth.store).into()
+    }
+
+    /// The component at the root, or `None` for variables and constants.
+    pub fn op(&self) -> Option<Op> {
+        self.prog.op(&self.synth.store)
+    }
+
+    /// The number of nodes in the program.
+    pub fn size(&self) -> usize {
+        self.prog.size(&self.synth.store)
+    }
+
+    /// What the program evaluates to, one value per example.
+    pub fn values(&self) -> Anies {
+        self.prog
+            .values_idx(&self.synth.store)
+            .to_anies(&self.synth.store)
+    }
+
+    /// An owned copy of the program as a tree.
+    pub fn to_ast(&self) -> Ast {
+        self.prog.to_ast(&self.synth.store)
+    }
+
+    /// The variable the result refers to, if any.
+    pub fn pointer(&self) -> Option<&'s str> {
+        let var = self.prog.pointer(&self.synth.store)?;
+        Some(&self.synth.store.var_map()[var])
+    }
+
+    /// The values the program needs its variables to have,
+    /// for the variables it reads.
+    pub fn pre_condition(&self) -> BTreeMap<String, Anies> {
+        self.condition(self.prog.conditions(&self.synth.store).0)
+    }
+
+    /// The values the variables it reads or writes have afterwards.
+    pub fn post_condition(&self) -> BTreeMap<String, Anies> {
+        self.condition(self.prog.conditions(&self.synth.store).1)
+    }
+
+    fn condition(&self, cond: &Condition) -> BTreeMap<String, Anies> {
+        let store = &self.synth.store;
+        cond.constrained()
+            .map(|(var, val)| (store.var_map()[var].clone(), val.to_anies(store)))
+            .collect()
+    }
+
+    /// The values every variable holds after running the program
+    /// from the task's before state.
+    pub fn after_state(&self) -> AfterState {
+        let store = &self.synth.store;
+        self.synth
+            .task
+            .after_values(self.prog.conditions(store).1, store)
+    }
+
+    /// How the program fails the task on `example`, if it does.
+    /// Only makes sense for programs that run from the before state.
+    pub fn mismatches(&self, example: usize) -> Vec<Mismatch> {
+        let task = &self.synth.task;
+        let after = self
+            .after_state()
+            .into_iter()
+            .map(|(var, values)| (var, values.get(example)))
+            .collect();
+        let mut mismatches = task.mismatches(example, &self.values().get(example), &after);
+        mismatches.extend(task.component_mismatches(&self.to_ast()));
+        mismatches
+    }
+
+    pub fn is_solution(&self) -> bool {
+        self.synth.task.is_solution(&self.prog, &self.synth.store)
+    }
+
+    /// The code in `lang`, rather than JavaScript. It's a whole statement
+    /// only if the task doesn't use its value, see `Lang::render_statement`.
+    pub fn render(&self, lang: Lang) -> std::result::Result<String, Unsupported> {
+        if self.synth.task.uses_output() {
+            lang.render(&self.to_ast())
+        } else {
+            lang.render_statement(&self.to_ast())
+        }
+    }
+
+    /// Writes the code between its pre- and post-condition.
+    pub fn pretty_print(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        self.pretty_print_code(out, &self.code())
+    }
+
+    /// Like `pretty_print`, with `code` in place of the program's own.
+    pub(crate) fn pretty_print_code(&self, out: &mut dyn Write, code: &str) -> std::io::Result<()> {
+        let store = &self.synth.store;
+        let (pre, post) = self.prog.conditions(store);
+        pre.pretty_print(out, store)?;
+        write!(out, "\n{code}\n")?;
+        post.pretty_print(out, store)
+    }
+}
+
+/// Enumerates programs within some limits, see `Synthesizer::programs`.
+pub struct Enumeration<'s> {
+    synth: &'s mut Synthesizer,
+    limits: Limits,
+    solutions_only: bool,
+    stop: Option<Stop>,
+}
+
+impl Enumeration<'_> {
+    /// Why we stopped, once we have.
+    pub fn stop(&self) -> Option<Stop> {
+        self.stop
+    }
+
+    pub fn synth(&self) -> &Synthesizer {
+        self.synth
+    }
+}
+
+impl Iterator for Enumeration<'_> {
+    type Item = Snippet;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stop.is_some() {
+            return None;
+        }
+
+        loop {
+            let prog = match self.synth.next_within(&self.limits) {
+                Ok(prog) => prog,
+                Err(stop) => {
+                    self.stop = Some(stop);
+                    return None;
+                }
+            };
+
+            if !self.solutions_only || self.synth.task.is_solution(&prog, &self.synth.store) {
+                return Some(self.synth.snippet(prog));
+            }
+        }
+    }
+}
 
 pub struct Synthesizer {
+    id: usize,
     vocab: Vocab,
     store: Bank,
     task: SynthesisTask,
+    /// The variables and constants we started with, and the literals
+    /// we've parsed since, by their code.
+    leaves: HashMap<String, AnyProg>,
     curr_enum: Box<dyn Enumerator>,
     curr_level: Level,
     curr_vocab: usize,
+    /// How many programs the bank held when we started the current level.
+    level_start: usize,
+    /// Set once no level after the current one could add anything.
+    exhausted: bool,
+    /// Where iterating over the synthesizer stops.
+    limits: Limits,
+    /// Why iterating over the synthesizer last stopped.
+    stop: Option<Stop>,
 }
 
 impl Synthesizer {
@@ -25,88 +305,548 @@ impl Synthesizer {
         // Building the store takes a few steps
         let mut store = Bank::new(task.examples(), task.var_map.clone());
 
+        // 2. Add the frame and component constraints
+        for (name, _, var_idx) in task.variables() {
+            if task.is_read_only(name) {
+                store.set_read_only(var_idx);
+            }
+        }
+        for component in task.must_use() {
+            store.constrain(component.clone(), true);
+        }
+        for component in task.must_not_use() {
+            store.constrain(component.clone(), false);
+        }
+
         // 3. Add the variables
+        let mut leaves = HashMap::new();
         for (name, values, var_idx) in task.variables() {
             match values {
                 Anies::Int(values) => {
-                    store
+                    let idx = store
                         .put_variable(name.clone(), values.clone(), var_idx)
                         .expect("Int variable already exists.");
+                    leaves.insert(name.clone(), idx.into());
                 }
                 Anies::Str(values) => {
-                    store
+                    let idx = store
                         .put_variable(name.clone(), values.clone(), var_idx)
                         .expect("Str variable already exists.");
+                    leaves.insert(name.clone(), idx.into());
                 }
                 Anies::IntArray(values) => {
-                    store
+                    let idx = store
                         .put_variable(name.clone(), values.clone(), var_idx)
                         .expect("IntArray variable already exists.");
+                    leaves.insert(name.clone(), idx.into());
                 }
             }
         }
 
         // 4. Add the constants
-        for con in vocab::constants() {
+        for con in task.constants() {
             match con {
                 ConstVal::Int(code, val) => {
-                    store
-                        .put_constant(code, val)
+                    let idx = store
+                        .put_constant(code, *val)
                         .expect("Constant {code} already exists.");
+                    leaves.insert(code.clone(), idx.into());
                 }
                 ConstVal::Str(code, val) => {
-                    store
-                        .put_constant(code, val)
+                    let idx = store
+                        .put_constant(code, val.clone())
                         .expect("Constant {code} already exists.");
+                    leaves.insert(code.clone(), idx.into());
                 }
                 ConstVal::IntArray(code, val) => {
-                    store
-                        .put_constant(code, val)
+                    let idx = store
+                        .put_constant(code, val.clone())
                         .expect("Constant {code} already exists.");
+                    leaves.insert(code.clone(), idx.into());
                 }
             }
         }
 
-        let curr_enum = vocab[curr_vocab].enumerator(curr_level, &store);
+        let curr_enum = vocab.builders()[curr_vocab].enumerator(curr_level, &store);
+        let level_start = Type::ALL.into_iter().map(|ty| store.count(ty)).sum();
 
         Self {
+            id: NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed),
             vocab,
             store,
             task,
+            leaves,
             curr_enum,
             curr_level,
             curr_vocab,
+            level_start,
+            exhausted: false,
+            limits: Limits::default(),
+            stop: None,
         }
     }
 
+    /// Sets where iterating over the synthesizer stops.
+    /// By default, that's when it runs out of levels or programs.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.set_limits(limits);
+        self
+    }
+
+    /// Like `with_limits`, letting an iteration that stopped carry on
+    /// with new limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.stop = None;
+    }
+
+    /// Why iterating over the synthesizer stopped, once it has.
+    pub fn stop(&self) -> Option<Stop> {
+        self.stop
+    }
+
     #[inline]
-    pub fn store(&self) -> &Bank {
+    pub(crate) fn store(&self) -> &Bank {
         &self.store
     }
 
-    pub fn next(&mut self) -> Box<AnyProg> {
+    #[inline]
+    pub fn task(&self) -> &SynthesisTask {
+        &self.task
+    }
+
+    /// The level we're currently enumerating.
+    #[inline]
+    pub fn level(&self) -> u8 {
+        self.curr_level.into()
+    }
+
+    /// How many programs of type `ty` are in the bank.
+    pub fn bank_size(&self, ty: Type) -> usize {
+        self.store.count(ty)
+    }
+
+    /// How many programs are in the bank, of all types.
+    pub fn total_bank_size(&self) -> usize {
+        Type::ALL.into_iter().map(|ty| self.store.count(ty)).sum()
+    }
+
+    /// Looks at a snippet this synthesizer found.
+    ///
+    /// # Panics
+    ///
+    /// If `snippet` came from another synthesizer.
+    pub fn get(&self, snippet: Snippet) -> SnippetRef<'_> {
+        assert_eq!(
+            snippet.owner, self.id,
+            "This snippet came from another synthesizer."
+        );
+        self.view(snippet.prog)
+    }
+
+    pub(crate) fn view(&self, prog: AnyProg) -> SnippetRef<'_> {
+        SnippetRef { prog, synth: self }
+    }
+
+    pub(crate) fn snippet(&self, prog: AnyProg) -> Snippet {
+        Snippet {
+            prog,
+            owner: self.id,
+        }
+    }
+
+    /// Every program we enumerate within `limits`, in order.
+    /// Variables and constants aren't included.
+    pub fn programs(&mut self, limits: &Limits) -> Enumeration<'_> {
+        Enumeration {
+            synth: self,
+            limits: limits.clone(),
+            solutions_only: false,
+            stop: None,
+        }
+    }
+
+    /// Like `programs`, but only the ones that satisfy the task.
+    pub fn results(&mut self, limits: &Limits) -> Enumeration<'_> {
+        Enumeration {
+            synth: self,
+            limits: limits.clone(),
+            solutions_only: true,
+            stop: None,
+        }
+    }
+
+    /// The best `k` solutions within `limits`, including ones we found
+    /// before, enumerating further if we don't know of enough yet.
+    pub fn best(&mut self, k: usize, limits: &Limits) -> (Vec<Snippet>, Stop) {
+        self.best_by(k, limits, &default_ranking)
+    }
+
+    /// Like `best`, but ranked by `rank` rather than `default_ranking`.
+    /// Rankings that don't prefer lower levels may miss better solutions
+    /// above the level of the `k`th one we find, see `solutions`.
+    pub fn best_by(&mut self, k: usize, limits: &Limits, rank: &Ranking) -> (Vec<Snippet>, Stop) {
+        let (found, stop) = self.best_solutions(k, limits, rank);
+        (
+            found.into_iter().map(|prog| self.snippet(prog)).collect(),
+            stop,
+        )
+    }
+
+    /// Fills the holes in `sketch` with `fillers`, in order, and evaluates
+    /// it into the bank. Also gives the code of the filled sketch, which
+    /// may differ from that of the program we get back.
+    pub(crate) fn fill(
+        &mut self,
+        sketch: &Sketch,
+        fillers: &[AnyProg],
+    ) -> Option<(AnyProg, String)> {
+        let ast = sketch.fill(&mut fillers.iter().map(|prog| prog.to_ast(&self.store)))?;
+        let mut state = Condition::empty(self.store.variables());
+        let prog = self.eval_ast(&ast, &Vocab::all(), &mut state).ok()?;
+        let code = Lang::Js
+            .render(&ast)
+            .expect("Every component has JavaScript code.");
+        Some((prog, code))
+    }
+
+    /// Parses `code` as a sketch: a JavaScript expression like `parse`
+    /// takes, with `??` holes for `fill_sketch` to fill, e.g. `s[?? + 1]`.
+    pub fn sketch(&self, code: &str) -> std::result::Result<Sketch, ParseError> {
+        parse::parse_sketch(code, &|name| self.var_type(name))
+    }
+
+    /// The fillings of `sketch`'s holes with programs from the bank that
+    /// satisfy the task, roughly in order of level, growing the bank
+    /// within `limits` as we go.
+    pub fn fill_sketch(&mut self, sketch: Sketch, limits: &Limits) -> Fillings<'_> {
+        Fillings::new(self, sketch, limits.clone())
+    }
+
+    fn var_type(&self, name: &str) -> Option<Type> {
+        self.store
+            .var_map()
+            .contains(&name.to_string())
+            .then(|| self.leaves[name].ty())
+    }
+
+    /// Parses the JavaScript expression `code` and evaluates it on the
+    /// examples into the bank, with the same components the enumerators
+    /// use, whether or not they're in our vocab. See `parse` for the
+    /// expressions we can read.
+    ///
+    /// Note that due to observational equivalence, the snippet may be
+    /// a different but equivalent program that was already in the bank.
+    pub fn parse(&mut self, code: &str) -> std::result::Result<Snippet, ParseError> {
+        let ast = parse::parse(code, &|name| self.var_type(name))?;
+        self.eval(&ast)
+    }
+
+    /// Evaluates an already parsed program, like `parse` does.
+    pub(crate) fn eval(&mut self, ast: &Ast) -> std::result::Result<Snippet, ParseError> {
+        let mut state = Condition::empty(self.store.variables());
+        let prog = self.eval_ast(ast, &Vocab::all(), &mut state)?;
+        Ok(self.snippet(prog))
+    }
+
+    /// Evaluates `ast` into the bank, left to right like JavaScript.
+    /// `state` holds the values the variables have so far, so that
+    /// reading `x` after `x++` sees the new value.
+    fn eval_ast(
+        &mut self,
+        ast: &Ast,
+        vocab: &Vocab,
+        state: &mut Condition,
+    ) -> std::result::Result<AnyProg, ParseError> {
+        let prog = match ast {
+            Ast::Var(name) => {
+                let pointer = self.task.pointer(name);
+                match state.get(pointer) {
+                    Some(val) => self.current_var(name, val, pointer),
+                    None => self.leaves[name],
+                }
+            }
+            Ast::Literal(value) => self.literal(value.clone()),
+            Ast::Op { op, args } => {
+                let args: Vec<AnyProg> = args
+                    .iter()
+                    .map(|arg| self.eval_ast(arg, vocab, state))
+                    .try_collect()?;
+                let builder = vocab
+                    .builders()
+                    .iter()
+                    .find(|builder| builder.op() == *op)
+                    .expect("Every component is in the full vocab.");
+                builder
+                    .apply(&args, &mut self.store)
+                    .ok_or_else(|| ParseError::Eval {
+                        code: Lang::Js
+                            .render(ast)
+                            .expect("Every component has JavaScript code."),
+                    })?
+            }
+        };
+
+        let (_, post) = prog.conditions(&self.store);
+        for (var, val) in post.constrained().collect::<Vec<_>>() {
+            *state = state.mutate_with_index(var, Some(val));
+        }
+        Ok(prog)
+    }
+
+    /// The variable `name` holding `val`, added to the bank if it's new.
+    fn current_var(&mut self, name: &str, val: AnyVal, pointer: Pointer) -> AnyProg {
+        let name = name.to_string();
+        match val {
+            AnyVal::Int(idx) => {
+                let values = self.store[idx].to_vec();
+                match self.store.put_variable(name, values, pointer) {
+                    Ok(idx) | Err(idx) => idx.into(),
+                }
+            }
+            AnyVal::Str(idx) => {
+                let values = self.store[idx].to_vec();
+                match self.store.put_variable(name, values, pointer) {
+                    Ok(idx) | Err(idx) => idx.into(),
+                }
+            }
+            AnyVal::IntArray(idx) => {
+                let values = self.store[idx].to_vec();
+                match self.store.put_variable(name, values, pointer) {
+                    Ok(idx) | Err(idx) => idx.into(),
+                }
+            }
+        }
+    }
+
+    /// The constant for `value`, added to the bank and to our leaves
+    /// if it's not one of the task's.
+    fn literal(&mut self, value: Any) -> AnyProg {
+        let constant = ConstVal::from(value);
+        if let Some(prog) = self.leaves.get(constant.code()) {
+            return *prog;
+        }
+
+        let put = match &constant {
+            ConstVal::Int(code, val) => any_put(self.store.put_constant(code, *val)),
+            ConstVal::Str(code, val) => any_put(self.store.put_constant(code, val.clone())),
+            ConstVal::IntArray(code, val) => any_put(self.store.put_constant(code, val.clone())),
+        };
+        match put {
+            Ok(prog) => {
+                self.leaves.insert(constant.code().to_string(), prog);
+                prog
+            }
+            // An equivalent program, like `1 + 1` for `2`.
+            Err(prog) => prog,
+        }
+    }
+
+    /// Swaps in a task that asks for something else, keeping the bank.
+    /// Gives the task back if it would need a different bank.
+    /// Programs we've already enumerated aren't enumerated again,
+    /// so see `known_solutions` for those.
+    pub fn retarget(&mut self, task: SynthesisTask) -> std::result::Result<(), Box<SynthesisTask>> {
+        if self.task.same_inputs(&task) {
+            self.task.take_spec(task);
+            Ok(())
+        } else {
+            Err(Box::new(task))
+        }
+    }
+
+    /// The programs already in the bank that satisfy the task,
+    /// leaving out any above the `limits`' level from earlier searches.
+    pub(crate) fn known_solutions(&self, limits: &Limits) -> Vec<AnyProg> {
+        Type::ALL
+            .into_iter()
+            .flat_map(|ty| (0..self.store.count(ty)).map(move |idx| AnyProg::from_index(ty, idx)))
+            .filter(|prog| prog.level(&self.store) <= limits.max_level)
+            .filter(|prog| self.task.is_solution(prog, &self.store))
+            .collect()
+    }
+
+    /// Collects up to `k` solutions, or as many as we find within `limits`,
+    /// sorted best-first by `rank`. Also returns why we stopped.
+    /// Once we have `k`, we finish enumerating their level before ranking,
+    /// so that they're the best of it, and not just the first we came
+    /// across. That makes the result exact for rankings that prefer lower
+    /// levels, like `default_ranking`.
+    /// Calling this again continues the search where it left off,
+    /// though solutions that didn't make the cut aren't given again.
+    pub(crate) fn solutions(
+        &mut self,
+        k: usize,
+        limits: &Limits,
+        rank: &Ranking,
+    ) -> (Vec<AnyProg>, Stop) {
+        if k == 0 {
+            return (Vec::new(), Stop::Found);
+        }
+
+        let mut solutions = Vec::with_capacity(k);
+        // The level of the `k`th solution, once we have it.
+        let mut last_level = None;
+
+        let stop = loop {
+            let prog = match self.next_within(limits) {
+                Ok(prog) => prog,
+                Err(stop) => break stop,
+            };
+
+            let level = prog.level(&self.store);
+            if self.task.is_solution(&prog, &self.store) {
+                solutions.push(prog);
+            }
+
+            match last_level {
+                Some(last) if level > last => break Stop::Found,
+                None if solutions.len() >= k => last_level = Some(level),
+                _ => (),
+            }
+        };
+
+        self.rank(&mut solutions, rank);
+        if solutions.len() < k {
+            return (solutions, stop);
+        }
+
+        // Running out of time or levels while finishing off the last one
+        // still leaves us with enough.
+        solutions.truncate(k);
+        (solutions, Stop::Found)
+    }
+
+    /// The best `k` solutions we know of, counting the ones already in the
+    /// bank, enumerating further only if we don't know of enough yet.
+    /// Unlike `solutions`, asking again gives the same answer,
+    /// unless the limits let us go deeper.
+    pub(crate) fn best_solutions(
+        &mut self,
+        k: usize,
+        limits: &Limits,
+        rank: &Ranking,
+    ) -> (Vec<AnyProg>, Stop) {
+        let mut found = self.known_solutions(limits);
+        let mut stop = Stop::Found;
+        if found.len() < k {
+            let (more, why) = self.solutions(k - found.len(), limits, rank);
+            found.extend(more);
+            stop = why;
+        }
+
+        self.rank(&mut found, rank);
+        found.truncate(k);
+        (found, stop)
+    }
+
+    fn rank(&self, programs: &mut [AnyProg], rank: &Ranking) {
+        programs.sort_by(|lhs, rhs| rank(&self.view(*lhs), &self.view(*rhs)));
+    }
+
+    /// Like `next`, but gives up once we're past `limits`,
+    /// returning why we stopped instead.
+    pub(crate) fn next_within(&mut self, limits: &Limits) -> std::result::Result<AnyProg, Stop> {
         loop {
-            match self.curr_enum.next(&mut self.store) {
-                Result::Some(prog) => return Box::new(prog),
-                Result::None => (), // try again
-                Result::Done => {
-                    // Move to next enumerator and try again!
-                    self.curr_vocab += 1;
-
-                    if self.vocab.len() <= self.curr_vocab {
-                        // We're out of vocabs. Go to next level and reset.
-                        self.curr_level.inc();
-                        self.curr_vocab = 0;
-                    }
+            if let Some(stop) = self.past(limits) {
+                return Err(stop);
+            }
 
-                    self.curr_enum =
-                        self.vocab[self.curr_vocab].enumerator(self.curr_level, &self.store);
+            if let Some(prog) = self.step() {
+                return Ok(prog);
+            }
+        }
+    }
+
+    /// Why we should stop here, if we're past `limits`.
+    fn past(&self, limits: &Limits) -> Option<Stop> {
+        if self.exhausted || self.curr_level > limits.max_level {
+            return Some(Stop::Exhausted);
+        }
+        if limits
+            .max_bank_size
+            .is_some_and(|size| self.total_bank_size() >= size)
+        {
+            return Some(Stop::BankFull);
+        }
+        if limits
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(Stop::TimedOut);
+        }
+        if limits
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(atomic::Ordering::Relaxed))
+        {
+            return Some(Stop::Cancelled);
+        }
+        None
+    }
+
+    /// Takes a single step of the current enumerator,
+    /// moving on to the next one if it's done.
+    fn step(&mut self) -> Option<AnyProg> {
+        match self.curr_enum.next(&mut self.store) {
+            Result::Some(prog) => Some(prog),
+            Result::None => None,
+            Result::Done => {
+                // Move to next enumerator and try again!
+                self.curr_vocab += 1;
+
+                if self.vocab.len() <= self.curr_vocab {
+                    // We're out of vocabs. Go to next level and reset.
+                    // Programs are one level above their highest argument, so
+                    // if this level added nothing, neither will any later one.
+                    let size = self.total_bank_size();
+                    match self.curr_level.checked_next() {
+                        Some(next) if size > self.level_start => self.curr_level = next,
+                        _ => {
+                            self.exhausted = true;
+                            return None;
+                        }
+                    }
+                    self.level_start = size;
+                    self.curr_vocab = 0;
                 }
+
+                self.curr_enum =
+                    self.vocab.builders()[self.curr_vocab].enumerator(self.curr_level, &self.store);
+                None
+            }
+        }
+    }
+}
+
+/// Enumerates programs until we're past the limits set with `with_limits`,
+/// or out of programs.
+impl Iterator for Synthesizer {
+    type Item = Snippet;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(stop) = self.past(&self.limits) {
+                self.stop = Some(stop);
+                return None;
+            }
+
+            if let Some(prog) = self.step() {
+                return Some(self.snippet(prog));
             }
         }
     }
 }
 
+/// The result of putting a program in the bank, of whatever type.
+fn any_put<T>(put: std::result::Result<PIdx<T>, PIdx<T>>) -> std::result::Result<AnyProg, AnyProg>
+where
+    T: Value,
+    AnyProg: From<PIdx<T>>,
+{
+    put.map(Into::into).map_err(Into::into)
+}
+
 pub trait Enumerator: std::fmt::Debug {
     fn next(&mut self, store: &mut Bank) -> Result<AnyProg>;
 }
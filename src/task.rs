@@ -1,37 +1,501 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::utils::Anies;
+use crate::ast::Ast;
+use crate::cond::{Pointer, PostCondition};
+use crate::store::Bank;
+use crate::utils::*;
+use crate::vocab::{self, ConstVal, Op};
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
 pub type VarMap = SmallVec<[String; 4]>;
 
+/// Something a solution can be required to, or forbidden from, using.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    Var(String),
+    /// A constant, by its code, e.g. `0` or `""`.
+    Const(String),
+    Op(Op),
+}
+
+impl Component {
+    /// Whether `ast` uses this component anywhere.
+    pub fn used_by(&self, ast: &Ast) -> bool {
+        let mut used = false;
+        ast.visit(&mut |node| {
+            used |= match (self, node) {
+                (Component::Var(var), Ast::Var(name)) => var == name,
+                (Component::Const(code), Ast::Literal(value)) => {
+                    ConstVal::from(value.clone()).code() == code
+                }
+                (Component::Op(op), Ast::Op { op: other, .. }) => op == other,
+                _ => false,
+            }
+        });
+        used
+    }
+}
+
+/// The values every variable holds after running a program,
+/// one per example.
+pub type AfterState = BTreeMap<String, Anies>;
+
+/// A user-supplied check over a candidate's values (one per example)
+/// and the after state it leaves, for when we know a property of the
+/// solution rather than its exact output.
+pub type Predicate<T> = Box<dyn Fn(&[T], &AfterState) -> bool>;
+
+pub enum AnyPredicate {
+    Int(Predicate<Int>),
+    Str(Predicate<Str>),
+    IntArray(Predicate<IntArray>),
+}
+
+impl AnyPredicate {
+    /// Runs the predicate on `prog`, which leaves `after`.
+    /// Programs of a different type never satisfy it.
+    pub(crate) fn check(&self, prog: &AnyProg, after: &AfterState, store: &Bank) -> bool {
+        match (self, prog) {
+            (AnyPredicate::Int(pred), AnyProg::Int(idx)) => pred(store[*idx].values(store), after),
+            (AnyPredicate::Str(pred), AnyProg::Str(idx)) => pred(store[*idx].values(store), after),
+            (AnyPredicate::IntArray(pred), AnyProg::IntArray(idx)) => {
+                pred(store[*idx].values(store), after)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<Predicate<Int>> for AnyPredicate {
+    fn from(value: Predicate<Int>) -> Self {
+        AnyPredicate::Int(value)
+    }
+}
+
+impl From<Predicate<Str>> for AnyPredicate {
+    fn from(value: Predicate<Str>) -> Self {
+        AnyPredicate::Str(value)
+    }
+}
+
+impl From<Predicate<IntArray>> for AnyPredicate {
+    fn from(value: Predicate<IntArray>) -> Self {
+        AnyPredicate::IntArray(value)
+    }
+}
+
+/// One way a program fails a task on some example.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// It evaluates to `actual` rather than `expected`.
+    Output { expected: Any, actual: Any },
+    /// It leaves `var` holding `actual` rather than `expected`.
+    After {
+        var: String,
+        expected: Any,
+        actual: Any,
+    },
+    /// It evaluates to a forbidden value.
+    ForbiddenOutput { value: Any },
+    /// It leaves `var` holding a forbidden value.
+    ForbiddenAfter { var: String, value: Any },
+    /// It has no value, like `s[5]` with a shorter `s`.
+    Undefined,
+    /// It changes `var`, which is read-only.
+    ReadOnly { var: String },
+    /// It uses a component the task forbids.
+    Forbidden { component: Component },
+    /// It doesn't use a component the task requires.
+    Missing { component: Component },
+}
+
 pub struct SynthesisTask {
     /// Map each variable to a vector index,
     /// so we can use vecs instead of HashMaps
     /// to keep state.
-    pub var_map: VarMap, // TODO No! Bad Crab!
+    pub(crate) var_map: VarMap, // TODO No! Bad Crab!
     before_state: HashMap<String, Anies>,
+    output: Option<MaybeAnies>,
+    after_state: HashMap<String, MaybeAnies>,
+    predicates: Vec<AnyPredicate>,
+    /// Values solutions must *not* produce, as (example, value).
+    forbidden_outputs: Vec<(usize, Any)>,
+    /// Values variables must *not* hold afterwards, as (example, variable, value).
+    forbidden_after_state: Vec<(usize, String, Any)>,
+    /// Variables solutions may read, but never mutate.
+    read_only: HashSet<String>,
+    must_use: Vec<Component>,
+    must_not_use: Vec<Component>,
+    constants: Vec<ConstVal>,
     examples: usize,
 }
 
 impl SynthesisTask {
     pub fn new(before_state: HashMap<String, Anies>, examples: usize) -> Self {
-        let var_map = before_state.keys().map(|n| n.clone()).collect();
+        let var_map = before_state.keys().cloned().collect();
         Self {
             var_map,
             examples,
             before_state,
+            output: None,
+            after_state: HashMap::new(),
+            predicates: Vec::new(),
+            forbidden_outputs: Vec::new(),
+            forbidden_after_state: Vec::new(),
+            read_only: HashSet::new(),
+            must_use: Vec::new(),
+            must_not_use: Vec::new(),
+            constants: vocab::constants(),
         }
     }
 
+    /// Sets the values a solution must evaluate to, one per example.
+    /// Examples set to `None` are not checked.
+    pub fn with_output(mut self, output: impl Into<MaybeAnies>) -> Self {
+        let output = output.into();
+        debug_assert_eq!(output.len(), self.examples);
+        self.output = Some(output);
+        self
+    }
+
+    /// Sets the values `var` must hold after running a solution.
+    ///
+    /// This is how we synthesize statements like `x++` or `arr.push(y)`:
+    /// with no output set, only the resulting state is checked.
+    /// Variables with no after state, and examples set to `None`,
+    /// are not checked.
+    pub fn with_after_state(mut self, var: String, values: impl Into<MaybeAnies>) -> Self {
+        let values = values.into();
+        debug_assert!(self.before_state.contains_key(&var));
+        debug_assert_eq!(values.len(), self.examples);
+        self.after_state.insert(var, values);
+        self
+    }
+
+    /// Adds a predicate every solution must satisfy, on top of
+    /// any output or after state. The type of the predicate's values
+    /// also fixes the type of the solution, e.g. `|values: &[Int], after| ..`.
+    pub fn with_predicate<T>(mut self, pred: impl Fn(&[T], &AfterState) -> bool + 'static) -> Self
+    where
+        AnyPredicate: From<Predicate<T>>,
+    {
+        let pred: Predicate<T> = Box::new(pred);
+        self.predicates.push(pred.into());
+        self
+    }
+
+    /// Rejects any program that evaluates to `value` on `example`.
+    pub fn with_forbidden_output(mut self, example: usize, value: Any) -> Self {
+        debug_assert!(example < self.examples);
+        self.forbidden_outputs.push((example, value));
+        self
+    }
+
+    /// Rejects any program that leaves `var` holding `value` on `example`.
+    ///
+    /// # Panics
+    ///
+    /// If the task has no variable `var`, since nothing could ever be rejected.
+    pub fn with_forbidden_after_state(mut self, example: usize, var: String, value: Any) -> Self {
+        debug_assert!(example < self.examples);
+        assert!(
+            self.before_state.contains_key(&var),
+            "Can't forbid an after state for `{var}`, which isn't a variable of this task."
+        );
+        self.forbidden_after_state.push((example, var, value));
+        self
+    }
+
+    /// Declares `var` as read-only (like `const`), so the synthesizer
+    /// never mutates it.
+    pub fn with_read_only(mut self, var: String) -> Self {
+        debug_assert!(self.before_state.contains_key(&var));
+        self.read_only.insert(var);
+        self
+    }
+
+    pub fn is_read_only(&self, var: &str) -> bool {
+        self.read_only.contains(var)
+    }
+
+    /// Only accept solutions that use `component` somewhere.
+    pub fn with_must_use(mut self, component: Component) -> Self {
+        self.must_use.push(component);
+        self
+    }
+
+    /// Never enumerate programs that use `component` anywhere.
+    pub fn with_must_not_use(mut self, component: Component) -> Self {
+        self.must_not_use.push(component);
+        self
+    }
+
+    /// Replaces the default constants from the vocab.
+    pub fn with_constants(mut self, constants: Vec<ConstVal>) -> Self {
+        self.constants = constants;
+        self
+    }
+
+    pub fn constants(&self) -> &[ConstVal] {
+        &self.constants
+    }
+
+    pub fn must_use(&self) -> &[Component] {
+        &self.must_use
+    }
+
+    pub fn must_not_use(&self) -> &[Component] {
+        &self.must_not_use
+    }
+
+    /// Whether `other` would build the same bank as this task:
+    /// the same variables, values, constants and constraints,
+    /// even if it asks for something else.
+    pub fn same_inputs(&self, other: &SynthesisTask) -> bool {
+        self.examples == other.examples
+            && self.before_state == other.before_state
+            && self.read_only == other.read_only
+            && self.must_use == other.must_use
+            && self.must_not_use == other.must_not_use
+            && self.constants == other.constants
+    }
+
+    /// Replaces what this task asks for with what `other` asks for,
+    /// keeping our variables (and their pointers).
+    /// Only makes sense if the two tasks have the `same_inputs`.
+    pub(crate) fn take_spec(&mut self, other: SynthesisTask) {
+        debug_assert!(self.same_inputs(&other));
+        self.output = other.output;
+        self.after_state = other.after_state;
+        self.predicates = other.predicates;
+        self.forbidden_outputs = other.forbidden_outputs;
+        self.forbidden_after_state = other.forbidden_after_state;
+    }
+
     pub fn examples(&self) -> usize {
         self.examples
     }
 
-    pub fn variables(&self) -> impl Iterator<Item = (&String, &Anies, usize)> {
+    pub fn output(&self) -> Option<&MaybeAnies> {
+        self.output.as_ref()
+    }
+
+    pub fn after_state(&self) -> &HashMap<String, MaybeAnies> {
+        &self.after_state
+    }
+
+    pub fn before_state(&self) -> &HashMap<String, Anies> {
+        &self.before_state
+    }
+
+    /// Whether the value of a solution matters, rather than only what it
+    /// does to the variables. Predicates may look at it, so they count.
+    pub fn uses_output(&self) -> bool {
+        self.output.is_some() || !self.predicates.is_empty() || !self.forbidden_outputs.is_empty()
+    }
+
+    /// How a program that evaluates to `result` and leaves the variables
+    /// holding `after` fails this task on `example`, if it does.
+    /// Like `is_solution`, but for a single example, and leaving out
+    /// the predicates, which only look at all the examples together,
+    /// and the components, see `component_mismatches`.
+    pub fn mismatches(
+        &self,
+        example: usize,
+        result: &Any,
+        after: &BTreeMap<String, Any>,
+    ) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+
+        if let Some(expected) = self.output.as_ref().and_then(|output| output.get(example)) {
+            if expected != *result {
+                mismatches.push(Mismatch::Output {
+                    expected,
+                    actual: result.clone(),
+                });
+            }
+        }
+
+        for (forbidden_example, value) in &self.forbidden_outputs {
+            if *forbidden_example == example && value == result {
+                mismatches.push(Mismatch::ForbiddenOutput {
+                    value: value.clone(),
+                });
+            }
+        }
+
+        for (var, actual) in after {
+            if self.is_read_only(var) && self.before_state[var].get(example) != *actual {
+                mismatches.push(Mismatch::ReadOnly { var: var.clone() });
+            }
+
+            let expected = self
+                .after_state
+                .get(var)
+                .and_then(|expected| expected.get(example));
+            if let Some(expected) = expected {
+                if expected != *actual {
+                    mismatches.push(Mismatch::After {
+                        var: var.clone(),
+                        expected,
+                        actual: actual.clone(),
+                    });
+                }
+            }
+
+            let forbidden = self
+                .forbidden_after_state
+                .iter()
+                .filter(|(e, v, value)| *e == example && v == var && value == actual);
+            for (_, _, value) in forbidden {
+                mismatches.push(Mismatch::ForbiddenAfter {
+                    var: var.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+
+        mismatches
+    }
+
+    /// The forbidden components `ast` uses and the required ones it doesn't,
+    /// which fail it on every example.
+    pub fn component_mismatches(&self, ast: &Ast) -> Vec<Mismatch> {
+        let forbidden = self
+            .must_not_use
+            .iter()
+            .filter(|component| component.used_by(ast))
+            .map(|component| Mismatch::Forbidden {
+                component: component.clone(),
+            });
+        let missing = self
+            .must_use
+            .iter()
+            .filter(|component| !component.used_by(ast))
+            .map(|component| Mismatch::Missing {
+                component: component.clone(),
+            });
+        forbidden.chain(missing).collect()
+    }
+
+    /// The task restricted to its `example`th example, with nothing asked
+    /// of it, so any program that has a value there can be evaluated.
+    pub(crate) fn unconstrained_example(&self, example: usize) -> SynthesisTask {
+        let before_state = self
+            .before_state
+            .iter()
+            .map(|(var, values)| (var.clone(), values.get(example).into()))
+            .collect();
+        SynthesisTask::new(before_state, 1).with_constants(self.constants.clone())
+    }
+
+    /// The index of `var` in the var map.
+    pub(crate) fn pointer(&self, var: &str) -> Pointer {
+        self.var_map
+            .iter()
+            .position(|name| name == var)
+            .expect("Unknown variable.")
+    }
+
+    pub(crate) fn variables(&self) -> impl Iterator<Item = (&String, &Anies, usize)> {
         self.var_map
             .iter()
             .enumerate()
             .map(|(idx, name)| (name, &self.before_state[name], idx))
     }
+
+    /// Checks whether `prog` satisfies this task.
+    ///
+    /// A solution must be runnable from the before state, i.e. every variable
+    /// its pre-condition reads must hold its original values, and it must
+    /// evaluate to the expected output, leave the expected after state
+    /// on every example, and satisfy all the predicates. It must also avoid
+    /// every forbidden output and after state, use every required component
+    /// and no forbidden one.
+    pub(crate) fn is_solution(&self, prog: &AnyProg, store: &Bank) -> bool {
+        let uses = prog.uses(store);
+        if !store.satisfies(uses) || !store.allows(uses) {
+            return false;
+        }
+
+        let (pre, post) = prog.conditions(store);
+        let values = prog.values_idx(store);
+        let runnable = self
+            .variables()
+            .all(|(_, values, idx)| pre.get(idx).is_none_or(|val| values.matches(val, store)));
+
+        if !runnable {
+            return false;
+        }
+
+        if let Some(output) = &self.output {
+            if !output.matches(values, store) {
+                return false;
+            }
+        }
+
+        if self
+            .forbidden_outputs
+            .iter()
+            .any(|(example, value)| value.matches(values, *example, store))
+        {
+            return false;
+        }
+
+        let forbidden_after = self
+            .forbidden_after_state
+            .iter()
+            .any(|(example, var, value)| {
+                let idx = self.pointer(var);
+                match post.get(idx) {
+                    Some(val) => value.matches(val, *example, store),
+                    None => value.matches_anies(&self.before_state[var], *example),
+                }
+            });
+        if forbidden_after {
+            return false;
+        }
+
+        let expected_after = self.variables().all(|(name, before, idx)| {
+            let Some(after) = self.after_state.get(name) else {
+                return true;
+            };
+
+            // Variables missing from the post-condition were never touched,
+            // so they still hold their before values.
+            match post.get(idx) {
+                Some(val) => after.matches(val, store),
+                None => after.matches_anies(before),
+            }
+        });
+        if !expected_after {
+            return false;
+        }
+
+        // Predicates go last, since they need the whole after state.
+        if self.predicates.is_empty() {
+            return true;
+        }
+        let after = self.after_values(post, store);
+        self.predicates
+            .iter()
+            .all(|pred| pred.check(prog, &after, store))
+    }
+
+    /// The values every variable holds after a program with post-condition
+    /// `post` runs from the before state.
+    pub(crate) fn after_values(&self, post: &PostCondition, store: &Bank) -> AfterState {
+        self.variables()
+            .map(|(name, before, idx)| {
+                let values = match post.get(idx) {
+                    Some(val) => val.to_anies(store),
+                    None => before.clone(),
+                };
+                (name.clone(), values)
+            })
+            .collect()
+    }
 }
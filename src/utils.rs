@@ -1,6 +1,14 @@
 use std::marker::PhantomData;
 
-use crate::{ops::Program, store::Bank, Level, Pointer, PostCondition, PreCondition};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::Ast,
+    ops::Program,
+    store::{Bank, Store, Uses},
+    vocab::Op,
+    Level, Pointer, PostCondition, PreCondition,
+};
 
 pub type Int = i32;
 pub type Str = String;
@@ -68,7 +76,7 @@ impl<T: Value> From<VIdx<T>> for usize {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PIdx<T: Value> {
     i: usize,
     _phantom_data: PhantomData<T>,
@@ -145,18 +153,202 @@ impl<T: Value> std::ops::Sub<usize> for PIdx<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum Any {
     Int(Int),
     Str(Str),
+    IntArray(IntArray),
+}
+
+impl Any {
+    pub fn ty(&self) -> Type {
+        match self {
+            Any::Int(_) => Type::Int,
+            Any::Str(_) => Type::Str,
+            Any::IntArray(_) => Type::IntArray,
+        }
+    }
+
+    /// Checks whether the values stored at `val` hold this value on `example`.
+    /// Values of a different type never match.
+    pub(crate) fn matches(&self, val: AnyVal, example: usize, store: &Bank) -> bool {
+        match (self, val) {
+            (Any::Int(expected), AnyVal::Int(idx)) => &store.get_values(idx)[example] == expected,
+            (Any::Str(expected), AnyVal::Str(idx)) => &store.get_values(idx)[example] == expected,
+            (Any::IntArray(expected), AnyVal::IntArray(idx)) => {
+                &store.get_values(idx)[example] == expected
+            }
+            _ => false,
+        }
+    }
+
+    /// Like `matches`, but for values that are not in the store.
+    pub fn matches_anies(&self, values: &Anies, example: usize) -> bool {
+        match (self, values) {
+            (Any::Int(expected), Anies::Int(values)) => &values[example] == expected,
+            (Any::Str(expected), Anies::Str(values)) => &values[example] == expected,
+            (Any::IntArray(expected), Anies::IntArray(values)) => &values[example] == expected,
+            _ => false,
+        }
+    }
 }
 
+/// Values of one type, one per example.
+/// When deserialized, the type is inferred from the values,
+/// trying each variant in order.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum Anies {
     Int(Vec<Int>),
     Str(Vec<Str>),
     IntArray(Vec<IntArray>),
 }
 
+impl Anies {
+    pub fn len(&self) -> usize {
+        match self {
+            Anies::Int(values) => values.len(),
+            Anies::Str(values) => values.len(),
+            Anies::IntArray(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn ty(&self) -> Type {
+        match self {
+            Anies::Int(_) => Type::Int,
+            Anies::Str(_) => Type::Str,
+            Anies::IntArray(_) => Type::IntArray,
+        }
+    }
+
+    /// The value on `example`.
+    pub fn get(&self, example: usize) -> Any {
+        match self {
+            Anies::Int(values) => Any::Int(values[example]),
+            Anies::Str(values) => Any::Str(values[example].clone()),
+            Anies::IntArray(values) => Any::IntArray(values[example].clone()),
+        }
+    }
+
+    /// Checks whether the values stored at `val` are exactly these values.
+    /// Values of a different type never match.
+    pub(crate) fn matches(&self, val: AnyVal, store: &Bank) -> bool {
+        match (self, val) {
+            (Anies::Int(expected), AnyVal::Int(idx)) => store.get_values(idx) == expected,
+            (Anies::Str(expected), AnyVal::Str(idx)) => store.get_values(idx) == expected,
+            (Anies::IntArray(expected), AnyVal::IntArray(idx)) => store.get_values(idx) == expected,
+            _ => false,
+        }
+    }
+}
+
+/// Like `Anies`, but any example may be left as `None`,
+/// meaning we don't care about its value.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MaybeAnies {
+    Int(Vec<Option<Int>>),
+    Str(Vec<Option<Str>>),
+    IntArray(Vec<Option<IntArray>>),
+}
+
+impl MaybeAnies {
+    pub fn len(&self) -> usize {
+        match self {
+            MaybeAnies::Int(values) => values.len(),
+            MaybeAnies::Str(values) => values.len(),
+            MaybeAnies::IntArray(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value on `example`, or `None` if we don't care about it.
+    pub fn get(&self, example: usize) -> Option<Any> {
+        match self {
+            MaybeAnies::Int(values) => values[example].map(Any::Int),
+            MaybeAnies::Str(values) => values[example].clone().map(Any::Str),
+            MaybeAnies::IntArray(values) => values[example].clone().map(Any::IntArray),
+        }
+    }
+
+    /// Checks the values stored at `val` against the specified examples.
+    /// Values of a different type never match.
+    pub(crate) fn matches(&self, val: AnyVal, store: &Bank) -> bool {
+        match (self, val) {
+            (MaybeAnies::Int(expected), AnyVal::Int(idx)) => {
+                Self::matches_slice(expected, store.get_values(idx))
+            }
+            (MaybeAnies::Str(expected), AnyVal::Str(idx)) => {
+                Self::matches_slice(expected, store.get_values(idx))
+            }
+            (MaybeAnies::IntArray(expected), AnyVal::IntArray(idx)) => {
+                Self::matches_slice(expected, store.get_values(idx))
+            }
+            _ => false,
+        }
+    }
+
+    /// Like `matches`, but for values that are not in the store.
+    pub fn matches_anies(&self, values: &Anies) -> bool {
+        match (self, values) {
+            (MaybeAnies::Int(expected), Anies::Int(values)) => {
+                Self::matches_slice(expected, values)
+            }
+            (MaybeAnies::Str(expected), Anies::Str(values)) => {
+                Self::matches_slice(expected, values)
+            }
+            (MaybeAnies::IntArray(expected), Anies::IntArray(values)) => {
+                Self::matches_slice(expected, values)
+            }
+            _ => false,
+        }
+    }
+
+    fn matches_slice<T: Value>(expected: &[Option<T>], values: &[T]) -> bool {
+        debug_assert_eq!(expected.len(), values.len());
+        expected
+            .iter()
+            .zip(values)
+            .all(|(expected, value)| expected.as_ref().is_none_or(|e| e == value))
+    }
+}
+
+impl From<Anies> for MaybeAnies {
+    fn from(value: Anies) -> Self {
+        match value {
+            Anies::Int(values) => MaybeAnies::Int(values.into_iter().map(Some).collect()),
+            Anies::Str(values) => MaybeAnies::Str(values.into_iter().map(Some).collect()),
+            Anies::IntArray(values) => MaybeAnies::IntArray(values.into_iter().map(Some).collect()),
+        }
+    }
+}
+
+impl From<Vec<Option<Int>>> for MaybeAnies {
+    fn from(value: Vec<Option<Int>>) -> Self {
+        MaybeAnies::Int(value)
+    }
+}
+
+impl From<Vec<Option<Str>>> for MaybeAnies {
+    fn from(value: Vec<Option<Str>>) -> Self {
+        MaybeAnies::Str(value)
+    }
+}
+
+impl From<Vec<Option<IntArray>>> for MaybeAnies {
+    fn from(value: Vec<Option<IntArray>>) -> Self {
+        MaybeAnies::IntArray(value)
+    }
+}
+
 impl From<Vec<Int>> for Anies {
     fn from(value: Vec<Int>) -> Self {
         Anies::Int(value)
@@ -175,6 +367,17 @@ impl From<Vec<IntArray>> for Anies {
     }
 }
 
+/// The values for a single example.
+impl From<Any> for Anies {
+    fn from(value: Any) -> Self {
+        match value {
+            Any::Int(value) => Anies::Int(vec![value]),
+            Any::Str(value) => Anies::Str(vec![value]),
+            Any::IntArray(value) => Anies::IntArray(vec![value]),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum AnyVal {
     Int(VIdx<Int>),
@@ -182,7 +385,29 @@ pub enum AnyVal {
     IntArray(VIdx<IntArray>),
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+impl AnyVal {
+    /// Copies the values stored at this index out of the store.
+    pub fn to_anies(self, store: &Bank) -> Anies {
+        match self {
+            AnyVal::Int(idx) => Anies::Int(store.get_values(idx).to_vec()),
+            AnyVal::Str(idx) => Anies::Str(store.get_values(idx).to_vec()),
+            AnyVal::IntArray(idx) => Anies::IntArray(store.get_values(idx).to_vec()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Type {
+    Int,
+    Str,
+    IntArray,
+}
+
+impl Type {
+    pub const ALL: [Type; 3] = [Type::Int, Type::Str, Type::IntArray];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum AnyProg {
     Int(PIdx<Int>),
     Str(PIdx<Str>),
@@ -190,6 +415,31 @@ pub enum AnyProg {
 }
 
 impl AnyProg {
+    /// The `idx`th program of type `ty` in the store.
+    pub fn from_index(ty: Type, idx: usize) -> Self {
+        match ty {
+            Type::Int => AnyProg::Int(idx.into()),
+            Type::Str => AnyProg::Str(idx.into()),
+            Type::IntArray => AnyProg::IntArray(idx.into()),
+        }
+    }
+
+    pub fn ty(&self) -> Type {
+        match self {
+            AnyProg::Int(_) => Type::Int,
+            AnyProg::Str(_) => Type::Str,
+            AnyProg::IntArray(_) => Type::IntArray,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            AnyProg::Int(prog) => (*prog).into(),
+            AnyProg::Str(prog) => (*prog).into(),
+            AnyProg::IntArray(prog) => (*prog).into(),
+        }
+    }
+
     pub fn code(&self, store: &Bank) -> String {
         match self {
             AnyProg::Int(prog) => store[*prog].code(store),
@@ -198,6 +448,14 @@ impl AnyProg {
         }
     }
 
+    pub fn values_idx(&self, store: &Bank) -> AnyVal {
+        match self {
+            AnyProg::Int(prog) => AnyVal::Int(store[*prog].values_idx()),
+            AnyProg::Str(prog) => AnyVal::Str(store[*prog].values_idx()),
+            AnyProg::IntArray(prog) => AnyVal::IntArray(store[*prog].values_idx()),
+        }
+    }
+
     pub fn conditions<'s>(&self, store: &'s Bank) -> (&'s PreCondition, &'s PostCondition) {
         match self {
             AnyProg::Int(prog) => store[*prog].conditions(),
@@ -221,6 +479,46 @@ impl AnyProg {
             AnyProg::IntArray(prog) => store[*prog].level(),
         }
     }
+
+    pub fn uses(&self, store: &Bank) -> Uses {
+        match self {
+            AnyProg::Int(prog) => store[*prog].uses(),
+            AnyProg::Str(prog) => store[*prog].uses(),
+            AnyProg::IntArray(prog) => store[*prog].uses(),
+        }
+    }
+
+    pub fn op(&self, store: &Bank) -> Option<Op> {
+        match self {
+            AnyProg::Int(prog) => store[*prog].op(),
+            AnyProg::Str(prog) => store[*prog].op(),
+            AnyProg::IntArray(prog) => store[*prog].op(),
+        }
+    }
+
+    pub fn size(&self, store: &Bank) -> usize {
+        match self {
+            AnyProg::Int(prog) => store[*prog].size(store),
+            AnyProg::Str(prog) => store[*prog].size(store),
+            AnyProg::IntArray(prog) => store[*prog].size(store),
+        }
+    }
+
+    pub fn precedence(&self, store: &Bank) -> u8 {
+        match self {
+            AnyProg::Int(prog) => store[*prog].precedence(),
+            AnyProg::Str(prog) => store[*prog].precedence(),
+            AnyProg::IntArray(prog) => store[*prog].precedence(),
+        }
+    }
+
+    pub fn to_ast(self, store: &Bank) -> Ast {
+        match self {
+            AnyProg::Int(prog) => store[prog].to_ast(store),
+            AnyProg::Str(prog) => store[prog].to_ast(store),
+            AnyProg::IntArray(prog) => store[prog].to_ast(store),
+        }
+    }
 }
 
 impl From<PIdx<Int>> for AnyProg {
@@ -240,3 +538,36 @@ impl From<PIdx<IntArray>> for AnyProg {
         Self::IntArray(value)
     }
 }
+
+impl TryFrom<AnyProg> for PIdx<Int> {
+    type Error = ();
+
+    fn try_from(value: AnyProg) -> Result<Self, Self::Error> {
+        match value {
+            AnyProg::Int(idx) => Ok(idx),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TryFrom<AnyProg> for PIdx<Str> {
+    type Error = ();
+
+    fn try_from(value: AnyProg) -> Result<Self, Self::Error> {
+        match value {
+            AnyProg::Str(idx) => Ok(idx),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TryFrom<AnyProg> for PIdx<IntArray> {
+    type Error = ();
+
+    fn try_from(value: AnyProg) -> Result<Self, Self::Error> {
+        match value {
+            AnyProg::IntArray(idx) => Ok(idx),
+            _ => Err(()),
+        }
+    }
+}
@@ -5,7 +5,7 @@ pub(crate) fn push_eval<T>(
     elem: &dyn Program<T>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)>
+) -> Evaluated<Int>
 where
     T: Value,
     Array<T>: Value,
@@ -49,7 +49,7 @@ pub(crate) fn deref_eval<T>(
     idx: &dyn Program<Int>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<T>, Option<Mutation>, Option<Pointer>)>
+) -> Evaluated<T>
 where
     T: Value,
     Array<T>: Value,
@@ -60,7 +60,7 @@ where
         .zip(idx.values(store))
         .map(|(arr, idx)| {
             if *idx >= 0 {
-                arr.get(*idx as usize).map(|x| x.clone())
+                arr.get(*idx as usize).cloned()
             } else {
                 None
             }
@@ -77,7 +77,7 @@ pub(crate) fn len_eval<T>(
     arr: &dyn Program<Array<T>>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)>
+) -> Evaluated<Int>
 where
     Array<T>: Value,
 {
@@ -100,7 +100,7 @@ pub(crate) fn bin_slice_eval<T>(
     idx: &dyn Program<Int>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Array<T>>, Option<Mutation>, Option<Pointer>)>
+) -> Evaluated<Array<T>>
 where
     T: Value,
     Array<T>: Value,
@@ -7,7 +7,7 @@ pub(crate) fn sum_eval(
     rhs: &dyn Program<Int>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)> {
+) -> Evaluated<Int> {
     let lhs_vals = lhs.values(store);
     let rhs_vals = rhs.values(store);
     let rs = lhs_vals
@@ -27,7 +27,7 @@ pub(crate) fn sub_eval(
     rhs: &dyn Program<Int>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)> {
+) -> Evaluated<Int> {
     let rs = lhs
         .values(store)
         .iter()
@@ -41,11 +41,7 @@ pub(crate) fn sub_code(lhs: &str, rhs: &str) -> String {
     format!("{lhs} - {rhs}")
 }
 
-pub(crate) fn minus_eval(
-    arg: &dyn Program<Int>,
-    _: &Condition,
-    store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)> {
+pub(crate) fn minus_eval(arg: &dyn Program<Int>, _: &Condition, store: &Bank) -> Evaluated<Int> {
     let rs = arg
         .values(store)
         .iter()
@@ -58,11 +54,7 @@ pub(crate) fn minus_code(arg: &str) -> String {
     format!("-{arg}")
 }
 
-pub(crate) fn inc_eval(
-    arg: &dyn Program<Int>,
-    _: &Condition,
-    store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)> {
+pub(crate) fn inc_eval(arg: &dyn Program<Int>, _: &Condition, store: &Bank) -> Evaluated<Int> {
     if let Some(pointer) = arg.pointer() {
         // This is a postscript increment (x++),
         // so we return the original value, but mutate the state
@@ -1,5 +1,8 @@
-use crate::store::Bank;
+use serde::{Deserialize, Serialize};
+
+use crate::store::{Bank, MaxIdx, MaxPIdx, Store};
 use crate::synth::Enumerator;
+use crate::task::Component;
 use crate::{utils::*, Level};
 use crate::{BinBuilder, UniBuilder};
 
@@ -7,37 +10,270 @@ mod array;
 mod int;
 mod str;
 
-pub type Vocab = Vec<Builder>;
+/// The components the synthesizer builds programs from.
+pub struct Vocab {
+    builders: Vec<Builder>,
+}
+
+impl Vocab {
+    /// Every component we have.
+    pub fn all() -> Self {
+        vocab()
+    }
+
+    /// See `select`.
+    pub fn select<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        select(names)
+    }
+
+    /// The components in this vocab, in the order we enumerate them.
+    pub fn ops(&self) -> Vec<Op> {
+        self.builders.iter().map(Builder::op).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.builders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.builders.is_empty()
+    }
+
+    pub(crate) fn builders(&self) -> &[Builder] {
+        &self.builders
+    }
+}
+
+impl FromIterator<Op> for Vocab {
+    /// Picks the given components from the full vocab.
+    fn from_iter<T: IntoIterator<Item = Op>>(ops: T) -> Self {
+        let ops: Vec<Op> = ops.into_iter().collect();
+        let builders = vocab()
+            .builders
+            .into_iter()
+            .filter(|builder| ops.contains(&builder.op()))
+            .collect();
+        Self { builders }
+    }
+}
+
+/// Identifies the components in the vocab.
+/// Variants are declared in order of preference,
+/// so simpler, side-effect free components sort first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Sum,
+    Sub,
+    Minus,
+    StrLen,
+    StrDeref,
+    ArrLen,
+    ArrDeref,
+    ArrSlice,
+    Inc,
+    ArrPush,
+}
+
+impl Op {
+    pub const ALL: [Op; 10] = [
+        Op::Sum,
+        Op::Sub,
+        Op::Minus,
+        Op::StrLen,
+        Op::StrDeref,
+        Op::ArrLen,
+        Op::ArrDeref,
+        Op::ArrSlice,
+        Op::Inc,
+        Op::ArrPush,
+    ];
+
+    /// The name we use for this component in task files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Sum => "sum",
+            Op::Sub => "sub",
+            Op::Minus => "minus",
+            Op::StrLen => "str_len",
+            Op::StrDeref => "str_deref",
+            Op::ArrLen => "arr_len",
+            Op::ArrDeref => "arr_deref",
+            Op::ArrSlice => "arr_slice",
+            Op::Inc => "inc",
+            Op::ArrPush => "arr_push",
+        }
+    }
+
+    /// The group of components this one belongs to.
+    pub fn group(&self) -> &'static str {
+        match self {
+            Op::Sum | Op::Sub | Op::Minus | Op::Inc => "int",
+            Op::StrLen | Op::StrDeref => "str",
+            Op::ArrLen | Op::ArrDeref | Op::ArrSlice | Op::ArrPush => "array",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Op::Minus | Op::Inc | Op::StrLen | Op::ArrLen => 1,
+            Op::Sum | Op::Sub | Op::StrDeref | Op::ArrDeref | Op::ArrSlice | Op::ArrPush => 2,
+        }
+    }
+
+    /// How tightly the component binds its arguments, as in JavaScript's
+    /// grammar, where member access binds tightest and `+` loosest.
+    /// Variables and constants are `ATOM`s.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Op::Sum | Op::Sub => 11,
+            Op::Minus => 14,
+            Op::Inc => 15,
+            Op::StrLen | Op::StrDeref | Op::ArrLen | Op::ArrDeref | Op::ArrSlice | Op::ArrPush => {
+                17
+            }
+        }
+    }
+
+    pub fn associativity(&self) -> Assoc {
+        match self {
+            Op::Minus => Assoc::Right,
+            Op::Inc => Assoc::None,
+            Op::Sum
+            | Op::Sub
+            | Op::StrLen
+            | Op::StrDeref
+            | Op::ArrLen
+            | Op::ArrDeref
+            | Op::ArrSlice
+            | Op::ArrPush => Assoc::Left,
+        }
+    }
+
+    /// The lowest precedence the `i`th argument can have without
+    /// needing parentheses.
+    pub fn arg_precedence(&self, i: usize) -> u8 {
+        let precedence = self.precedence();
+        match (self, self.associativity()) {
+            // Indices and call arguments are delimited already.
+            (Op::StrDeref | Op::ArrDeref | Op::ArrSlice | Op::ArrPush, _) if i == 1 => 0,
+            (_, Assoc::Left) if i == 0 => precedence,
+            (_, Assoc::Right) if i + 1 == self.arity() => precedence,
+            _ => precedence + 1,
+        }
+    }
+}
+
+/// The precedence of variables and constants, which never need parentheses.
+pub const ATOM: u8 = 20;
+
+/// How a chain of components with the same precedence groups:
+/// `x - y - z` is `(x - y) - z`, and `- -x` is `-(-x)`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Assoc {
+    Left,
+    Right,
+    /// Doesn't chain, like `x++`, which only applies to variables.
+    None,
+}
+
+impl std::str::FromStr for Op {
+    type Err = String;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Op::ALL
+            .into_iter()
+            .find(|op| op.name() == s)
+            .ok_or_else(|| format!("unknown component `{s}`"))
+    }
+}
+
+/// A constant, with its code and value.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ConstVal {
-    Int(&'static str, Int),
-    Str(&'static str, Str),
-    IntArray(&'static str, IntArray),
+    Int(String, Int),
+    Str(String, Str),
+    IntArray(String, IntArray),
+}
+
+impl ConstVal {
+    pub fn code(&self) -> &str {
+        match self {
+            ConstVal::Int(code, _) => code,
+            ConstVal::Str(code, _) => code,
+            ConstVal::IntArray(code, _) => code,
+        }
+    }
+}
+
+impl From<Any> for ConstVal {
+    /// Builds a constant with the code for its value.
+    fn from(value: Any) -> Self {
+        match value {
+            Any::Int(val) => ConstVal::Int(val.to_string(), val),
+            Any::Str(val) => {
+                // JSON string literals are valid JavaScript too.
+                let code = serde_json::to_string(&val).expect("Strings are always valid JSON.");
+                ConstVal::Str(code, val)
+            }
+            Any::IntArray(val) => ConstVal::IntArray(format!("{val:?}"), val),
+        }
+    }
 }
 
 pub fn constants() -> Vec<ConstVal> {
     vec![
-        ConstVal::Int("0", 0),
-        ConstVal::Int("1", 1),
-        ConstVal::Str("\"\"", "".to_string()),
-        ConstVal::Str("\" \"", " ".to_string()),
-        ConstVal::IntArray("[]", vec![]),
+        ConstVal::Int("0".to_string(), 0),
+        ConstVal::Int("1".to_string(), 1),
+        ConstVal::Str("\"\"".to_string(), "".to_string()),
+        ConstVal::Str("\" \"".to_string(), " ".to_string()),
+        ConstVal::IntArray("[]".to_string(), vec![]),
     ]
 }
 
 pub fn vocab() -> Vocab {
-    vec![
-        UniBuilder::new(&str::len_eval, &str::len_code).into(),
-        BinBuilder::new(&str::deref_eval, &str::deref_code).into(),
-        UniBuilder::new(&int::minus_eval, &int::minus_code).into(),
-        BinBuilder::new(&int::sum_eval, &int::sum_code).into(),
-        BinBuilder::new(&int::sub_eval, &int::sub_code).into(),
-        UniBuilder::new(&int::inc_eval, &int::inc_code).into(),
-        BinBuilder::new(&array::push_eval::<Int>, &array::push_code).into(),
-        BinBuilder::new(&array::deref_eval::<Int>, &array::deref_code).into(),
-        UniBuilder::new(&array::len_eval::<Int>, &array::len_code).into(),
-        BinBuilder::new(&array::bin_slice_eval::<Int>, &array::bin_slice_code).into(),
-    ]
+    let builders = vec![
+        UniBuilder::new(Op::StrLen, &str::len_eval, &str::len_code).into(),
+        BinBuilder::new(Op::StrDeref, &str::deref_eval, &str::deref_code).into(),
+        UniBuilder::new(Op::Minus, &int::minus_eval, &int::minus_code).into(),
+        BinBuilder::new(Op::Sum, &int::sum_eval, &int::sum_code).into(),
+        BinBuilder::new(Op::Sub, &int::sub_eval, &int::sub_code).into(),
+        UniBuilder::new(Op::Inc, &int::inc_eval, &int::inc_code).into(),
+        BinBuilder::new(Op::ArrPush, &array::push_eval::<Int>, &array::push_code).into(),
+        BinBuilder::new(Op::ArrDeref, &array::deref_eval::<Int>, &array::deref_code).into(),
+        UniBuilder::new(Op::ArrLen, &array::len_eval::<Int>, &array::len_code).into(),
+        BinBuilder::new(
+            Op::ArrSlice,
+            &array::bin_slice_eval::<Int>,
+            &array::bin_slice_code,
+        )
+        .into(),
+    ];
+
+    Vocab { builders }
+}
+
+/// Picks the components of the full vocab by name.
+/// Each name is either a component (e.g. `str_len`), a group of components
+/// (`int`, `str` or `array`), or `all`.
+pub fn select<S: AsRef<str>>(names: &[S]) -> Result<Vocab, String> {
+    let mut ops = Vec::new();
+    for name in names {
+        match name.as_ref() {
+            "all" => ops.extend(Op::ALL),
+            group @ ("int" | "str" | "array") => {
+                ops.extend(Op::ALL.into_iter().filter(|op| op.group() == group))
+            }
+            name => ops.push(name.parse()?),
+        }
+    }
+
+    let vocab: Vocab = ops.into_iter().collect();
+    if vocab.is_empty() {
+        Err("no components selected".to_string())
+    } else {
+        Ok(vocab)
+    }
 }
 
 pub enum Builder {
@@ -101,24 +337,116 @@ impl From<BinBuilder<IntArray, Int, IntArray>> for Builder {
 }
 
 impl Builder {
+    pub fn op(&self) -> Op {
+        match &self {
+            Builder::UnaryIntInt(builder) => builder.op(),
+            Builder::UnaryIntStr(builder) => builder.op(),
+            Builder::UnaryStrInt(builder) => builder.op(),
+            Builder::UnaryStrStr(builder) => builder.op(),
+            Builder::UnaryIntArrInt(builder) => builder.op(),
+            Builder::BinaryIntIntInt(builder) => builder.op(),
+            Builder::BinaryIntIntStr(builder) => builder.op(),
+            Builder::BinaryIntStrInt(builder) => builder.op(),
+            Builder::BinaryIntStrStr(builder) => builder.op(),
+            Builder::BinaryStrIntInt(builder) => builder.op(),
+            Builder::BinaryStrIntStr(builder) => builder.op(),
+            Builder::BinaryStrStrInt(builder) => builder.op(),
+            Builder::BinaryStrStrStr(builder) => builder.op(),
+            Builder::BinaryIntArrIntInt(builder) => builder.op(),
+            Builder::BinaryIntArrIntIntArr(builder) => builder.op(),
+        }
+    }
+
+    /// Applies this component to `args`, adding the result to the store.
+    /// Returns `None` if the arguments have the wrong types or number,
+    /// or if the component does not apply to them.
+    pub fn apply(&self, args: &[AnyProg], store: &mut Bank) -> Option<AnyProg> {
+        match &self {
+            Builder::UnaryIntInt(builder) => uni_apply(builder, args, store),
+            Builder::UnaryIntStr(builder) => uni_apply(builder, args, store),
+            Builder::UnaryStrInt(builder) => uni_apply(builder, args, store),
+            Builder::UnaryStrStr(builder) => uni_apply(builder, args, store),
+            Builder::UnaryIntArrInt(builder) => uni_apply(builder, args, store),
+            Builder::BinaryIntIntInt(builder) => bin_apply(builder, args, store),
+            Builder::BinaryIntIntStr(builder) => bin_apply(builder, args, store),
+            Builder::BinaryIntStrInt(builder) => bin_apply(builder, args, store),
+            Builder::BinaryIntStrStr(builder) => bin_apply(builder, args, store),
+            Builder::BinaryStrIntInt(builder) => bin_apply(builder, args, store),
+            Builder::BinaryStrIntStr(builder) => bin_apply(builder, args, store),
+            Builder::BinaryStrStrInt(builder) => bin_apply(builder, args, store),
+            Builder::BinaryStrStrStr(builder) => bin_apply(builder, args, store),
+            Builder::BinaryIntArrIntInt(builder) => bin_apply(builder, args, store),
+            Builder::BinaryIntArrIntIntArr(builder) => bin_apply(builder, args, store),
+        }
+    }
+
     pub fn enumerator(&self, level: Level, store: &Bank) -> Box<dyn Enumerator> {
         let max_idx = store.curr_max();
+        let uses = store.uses(&Component::Op(self.op()));
         match &self {
-            Builder::UnaryIntInt(builder) => builder.into_enum(level, max_idx),
-            Builder::UnaryIntStr(builder) => builder.into_enum(level, max_idx),
-            Builder::UnaryStrInt(builder) => builder.into_enum(level, max_idx),
-            Builder::UnaryStrStr(builder) => builder.into_enum(level, max_idx),
-            Builder::UnaryIntArrInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntIntInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntIntStr(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntStrInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntStrStr(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryStrIntInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryStrIntStr(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryStrStrInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryStrStrStr(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntArrIntInt(builder) => builder.into_enum(level, max_idx),
-            Builder::BinaryIntArrIntIntArr(builder) => builder.into_enum(level, max_idx),
+            Builder::UnaryIntInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::UnaryIntStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::UnaryStrInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::UnaryStrStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::UnaryIntArrInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntIntInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntIntStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntStrInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntStrStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryStrIntInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryStrIntStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryStrStrInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryStrStrStr(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntArrIntInt(builder) => builder.enumerator(level, max_idx, uses),
+            Builder::BinaryIntArrIntIntArr(builder) => builder.enumerator(level, max_idx, uses),
         }
     }
 }
+
+fn uni_apply<I, O>(
+    builder: &UniBuilder<I, O>,
+    args: &[AnyProg],
+    store: &mut Bank,
+) -> Option<AnyProg>
+where
+    I: Value,
+    O: Value,
+    Bank: Store<I>,
+    Bank: Store<O>,
+    PIdx<I>: TryFrom<AnyProg>,
+    PIdx<O>: Into<AnyProg>,
+    MaxPIdx: MaxIdx<I>,
+{
+    let [arg] = args else {
+        return None;
+    };
+    let arg = PIdx::<I>::try_from(*arg).ok()?;
+    builder.apply(arg, store).map(Into::into)
+}
+
+fn bin_apply<L, R, O>(
+    builder: &BinBuilder<L, R, O>,
+    args: &[AnyProg],
+    store: &mut Bank,
+) -> Option<AnyProg>
+where
+    L: Value,
+    R: Value,
+    O: Value,
+    Bank: Store<L>,
+    Bank: Store<R>,
+    Bank: Store<O>,
+    PIdx<L>: TryFrom<AnyProg>,
+    PIdx<R>: TryFrom<AnyProg>,
+    MaxPIdx: MaxIdx<L>,
+    MaxPIdx: MaxIdx<R>,
+    MaxPIdx: MaxIdx<O>,
+    AnyProg: From<PIdx<O>>,
+{
+    let [lhs, rhs] = args else {
+        return None;
+    };
+    let lhs = PIdx::<L>::try_from(*lhs).ok()?;
+    let rhs = PIdx::<R>::try_from(*rhs).ok()?;
+    builder.apply(lhs, rhs, store).map(Into::into)
+}
@@ -2,11 +2,7 @@ use crate::*;
 
 use self::store::Bank;
 
-pub(crate) fn len_eval(
-    arg: &dyn Program<Str>,
-    _: &Condition,
-    store: &Bank,
-) -> Option<(Vec<Int>, Option<Mutation>, Option<Pointer>)> {
+pub(crate) fn len_eval(arg: &dyn Program<Str>, _: &Condition, store: &Bank) -> Evaluated<Int> {
     let rs = arg.values(store).iter().map(|s| s.len() as i32).collect();
     Some((rs, None, None))
 }
@@ -20,7 +16,7 @@ pub(crate) fn deref_eval(
     rhs: &dyn Program<Int>,
     _: &Condition,
     store: &Bank,
-) -> Option<(Vec<Str>, Option<Mutation>, Option<Pointer>)> {
+) -> Evaluated<Str> {
     let rs = lhs
         .values(store)
         .iter()
@@ -0,0 +1,67 @@
+//! Checks the C API from C: that the header is up to date, and that a C
+//! program compiles and links against it.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+#[test]
+fn header_is_up_to_date() {
+    let dir = Path::new(MANIFEST_DIR);
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).expect("can read config");
+    let mut header = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(dir)
+        .with_config(config)
+        .generate()
+        .expect("can generate the header")
+        .write(&mut header);
+
+    let checked_in = std::fs::read(dir.join("include/sober.h")).expect("can read the header");
+    assert!(
+        header == checked_in,
+        "include/sober.h is out of date, regenerate it with \
+         `cbindgen --config cbindgen.toml -o include/sober.h`"
+    );
+}
+
+/// Where cargo put `libsober.so`, next to the `deps` directory we're in.
+fn lib_dir() -> PathBuf {
+    let exe = std::env::current_exe().expect("know where the test is");
+    exe.parent()
+        .and_then(Path::parent)
+        .expect("tests live in target/<profile>/deps")
+        .to_path_buf()
+}
+
+#[test]
+fn c_program_links_and_runs() {
+    let dir = Path::new(MANIFEST_DIR);
+    let lib_dir = lib_dir();
+    let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capi_smoke");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(dir.join("include"))
+        .arg(dir.join("tests/capi/smoke.c"))
+        .arg("-o")
+        .arg(&exe)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lsober")
+        .status()
+        .expect("can run the C compiler");
+    assert!(status.success());
+
+    let output = Command::new(&exe).output().expect("can run the C program");
+    let stdout = String::from_utf8(output.stdout).expect("output is UTF-8");
+    assert!(output.status.success(), "{stdout}");
+    assert_eq!(
+        stdout,
+        "x + 1\nstatus: 1\nerror: EOF while parsing an object at line 1 column 1\n"
+    );
+}
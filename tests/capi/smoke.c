@@ -0,0 +1,38 @@
+// Synthesizes `x + 1` through the C API, printing each solution on a line
+// of its own and then the status, or the error.
+#include <stdio.h>
+
+#include "sober.h"
+
+int main(void) {
+    char *error = NULL;
+    SoberTask *task = sober_task_from_json(
+        "{\"examples\":2,\"variables\":{\"x\":[1,2]},\"output\":[2,3],\"after\":{\"x\":[1,2]}}", &error);
+    if (!task) {
+        printf("error: %s\n", error);
+        sober_string_free(error);
+        return 1;
+    }
+
+    SoberSynthesizer *synth = sober_synthesizer_new(task, "all", &error);
+    sober_task_free(task);
+    if (!synth) {
+        printf("error: %s\n", error);
+        sober_string_free(error);
+        return 1;
+    }
+
+    SoberResults *results = sober_synthesizer_results(synth, 2, 0);
+    for (char *code; (code = sober_results_next(results)); sober_string_free(code))
+        puts(code);
+    printf("status: %d\n", sober_results_status(results));
+    sober_results_free(results);
+    sober_synthesizer_free(synth);
+
+    if (sober_task_from_json("{", &error)) {
+        return 1;
+    }
+    printf("error: %s\n", error);
+    sober_string_free(error);
+    return 0;
+}
@@ -0,0 +1,261 @@
+//! Runs the `sober` binary, checking its output and exit codes.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Writes `json` to a task file of its own under the target directory.
+fn task_file(name: &str, json: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let path = dir.join(format!("{name}.json"));
+    std::fs::write(&path, json).expect("can write task files");
+    path
+}
+
+fn sober(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sober"))
+        .args(args)
+        .output()
+        .expect("can run sober")
+}
+
+fn run(args: &[&str]) -> (i32, String) {
+    let output = sober(args);
+    let stdout = String::from_utf8(output.stdout).expect("output is UTF-8");
+    (output.status.code().expect("exited normally"), stdout)
+}
+
+#[test]
+fn evaluate_passes() {
+    let task = task_file(
+        "evaluate_passes",
+        r#"{"examples":2,"variables":{"x":[0,2]},"output":[0,2],"after":{"x":[1,3]}}"#,
+    );
+    let (code, stdout) = run(&["evaluate", task.to_str().unwrap(), "x++"]);
+    assert_eq!(code, 0);
+    assert!(stdout.ends_with("passes every example\n"), "{stdout}");
+}
+
+#[test]
+fn evaluate_reports_undefined_examples() {
+    let task = task_file(
+        "evaluate_undefined",
+        r#"{"examples":2,"variables":{"s":["abc","d"]},"output":["c","d"]}"#,
+    );
+    let (code, stdout) = run(&[
+        "evaluate",
+        "--format",
+        "plain",
+        task.to_str().unwrap(),
+        "s[2]",
+    ]);
+    assert_eq!(code, 5);
+    assert_eq!(
+        stdout,
+        "example 0: ok\n  result: \"c\"\nexample 1: fails\n  result: undefined\n\
+         fails 1 of 2 examples\n"
+    );
+}
+
+#[test]
+fn evaluate_reports_broken_constraints() {
+    let task = task_file(
+        "evaluate_constraints",
+        r#"{"examples":1,"variables":{"x":[1]},"read_only":["x"],
+            "must_not_use":[{"op":"inc"}],"must_use":[{"const":"0"}]}"#,
+    );
+    let (code, stdout) = run(&[
+        "evaluate",
+        "--format",
+        "plain",
+        task.to_str().unwrap(),
+        "x++",
+    ]);
+    assert_eq!(code, 5);
+    assert_eq!(
+        stdout,
+        "example 0: fails\n  result: 1\n  x: 1 -> 2, but it's read-only\n\
+         \x20 uses `inc`, which is forbidden\n  doesn't use `0`, which is required\n\
+         fails 1 of 1 examples\n"
+    );
+}
+
+#[test]
+fn evaluate_rejects_code_it_cannot_parse() {
+    let task = task_file(
+        "evaluate_parse_error",
+        r#"{"examples":1,"variables":{"x":[1]}}"#,
+    );
+    let output = sober(&["evaluate", task.to_str().unwrap(), "x +"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: unexpected end of input at column 4\n"
+    );
+}
+
+#[test]
+fn evaluate_takes_code_starting_with_a_minus() {
+    let task = task_file(
+        "evaluate_minus",
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[-1,-2]}"#,
+    );
+    let (code, stdout) = run(&[
+        "evaluate",
+        "--format",
+        "plain",
+        task.to_str().unwrap(),
+        "-x",
+    ]);
+    assert_eq!(code, 0);
+    assert!(stdout.ends_with("passes every example\n"), "{stdout}");
+}
+
+/// A `sober daemon` on a socket of its own, killed once dropped.
+struct Daemon {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Daemon {
+    fn start(name: &str) -> Self {
+        let socket = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.sock"));
+        let child = Command::new(env!("CARGO_BIN_EXE_sober"))
+            .arg("daemon")
+            .arg(&socket)
+            .spawn()
+            .expect("can run sober");
+        Self { child, socket }
+    }
+
+    /// Connects once the daemon is listening.
+    fn connect(&self) -> UnixStream {
+        let start = Instant::now();
+        loop {
+            match UnixStream::connect(&self.socket) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(Duration::from_secs(10)))
+                        .unwrap();
+                    return stream;
+                }
+                Err(_) if start.elapsed() < Duration::from_secs(10) => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                Err(e) => panic!("daemon didn't start: {e}"),
+            }
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Sends one request and reads back its response.
+fn request(client: &mut UnixStream, request: &str) -> Value {
+    writeln!(client, "{request}").unwrap();
+    let mut response = String::new();
+    BufReader::new(&*client)
+        .read_line(&mut response)
+        .expect("the daemon answers");
+    serde_json::from_str(&response).expect("responses are JSON")
+}
+
+#[test]
+fn daemon_serves_clients_while_another_is_idle() {
+    let daemon = Daemon::start("daemon_idle");
+    let _idle = daemon.connect();
+
+    let mut client = daemon.connect();
+    let response = request(
+        &mut client,
+        r#"{"task":{"examples":1,"variables":{"x":[1]},"output":[2]}}"#,
+    );
+    assert!(response["solutions"].is_array(), "{response}");
+}
+
+#[test]
+fn daemon_resumes_sessions_for_the_same_task() {
+    let daemon = Daemon::start("daemon_resume");
+    let mut client = daemon.connect();
+    let task = r#"{"examples":2,"variables":{"x":[1,2]},"output":[3,5]}"#;
+
+    let first = request(&mut client, &format!(r#"{{"task":{task},"max_level":1}}"#));
+    assert_eq!(first["resumed"], false, "{first}");
+    assert_eq!(first["solutions"], serde_json::json!([]), "{first}");
+
+    let second = request(&mut client, &format!(r#"{{"task":{task},"max_level":2}}"#));
+    assert_eq!(second["resumed"], true, "{second}");
+    assert_eq!(second["solutions"][0]["code"], "x + (x + 1)", "{second}");
+    // The bank we kept only grew.
+    for ty in ["int", "str", "int_array"] {
+        assert!(
+            second["bank"][ty].as_u64() >= first["bank"][ty].as_u64(),
+            "{first} {second}"
+        );
+    }
+}
+
+#[test]
+fn daemon_only_replaces_stale_sockets() {
+    let file = task_file("daemon_victim", "{}");
+    let output = sober(&["daemon", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "{}");
+
+    // Left behind by a listener that's gone.
+    let socket = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("daemon_stale.sock");
+    let _ = std::fs::remove_file(&socket);
+    drop(UnixListener::bind(&socket).unwrap());
+    let daemon = Daemon::start("daemon_stale");
+    let mut client = daemon.connect();
+    let response = request(
+        &mut client,
+        r#"{"task":{"examples":1,"variables":{"x":[1]},"output":[2]}}"#,
+    );
+    assert!(response["solutions"].is_array(), "{response}");
+}
+
+#[test]
+fn synth_exits_with_why_it_stopped() {
+    let task = task_file(
+        "synth_found",
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[2,3],"after":{"x":[1,2]}}"#,
+    );
+    let (code, stdout) = run(&["synth", task.to_str().unwrap()]);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("x + 1"), "{stdout}");
+
+    let task = task_file(
+        "synth_exhausted",
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[7,9],"constants":[]}"#,
+    );
+    let (code, _) = run(&["synth", "--max-level", "3", task.to_str().unwrap()]);
+    assert_eq!(code, 3);
+
+    let task = task_file(
+        "synth_timed_out",
+        r#"{"examples":2,"variables":{"x":[1,2],"s":["ab","c"]},"output":[-1000,1000]}"#,
+    );
+    let (code, _) = run(&["synth", "--timeout", "0.01", task.to_str().unwrap()]);
+    assert_eq!(code, 4);
+}
+
+#[test]
+fn exits_with_usage_and_other_errors() {
+    assert_eq!(run(&["synth"]).0, 2);
+    assert_eq!(run(&["synth", "--no-such-flag", "task.json"]).0, 2);
+    assert_eq!(run(&["frobnicate"]).0, 2);
+
+    let output = sober(&["synth", "no/such/task.json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.starts_with(b"error: "));
+}
@@ -0,0 +1,340 @@
+//! Searches on small tasks, through the public API.
+
+use sober::{
+    default_ranking, loader, AfterState, Anies, Any, AnyPredicate, Int, Lang, Limits, Predicate,
+    SnippetRef, Stop, Str, Synthesizer, Vocab,
+};
+
+fn synthesizer(json: &str) -> Synthesizer {
+    let task = loader::parse_task(json).expect("valid task");
+    Synthesizer::new(Vocab::all(), task)
+}
+
+/// The code of the best `k` solutions within `max_level`.
+fn best(synth: &mut Synthesizer, k: usize, max_level: u8) -> Vec<String> {
+    let (found, _) = synth.best(k, &Limits::new(max_level));
+    found
+        .into_iter()
+        .map(|snippet| synth.get(snippet).code())
+        .collect()
+}
+
+#[test]
+fn searches_without_strings_or_arrays() {
+    // Custom constants replace the default `""` and `[]`, leaving no
+    // strings or arrays for the components that take them.
+    let mut synth =
+        synthesizer(r#"{"examples":2,"variables":{"x":[1,2]},"output":[3,5],"constants":[1]}"#);
+    assert_eq!(best(&mut synth, 1, 3).len(), 1);
+
+    let mut synth =
+        synthesizer(r#"{"examples":2,"variables":{"x":[1,2]},"output":[7,9],"constants":[]}"#);
+    assert!(best(&mut synth, 1, 4).is_empty());
+}
+
+#[test]
+fn solutions_use_required_components() {
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,2],"must_use":[{"op":"sum"}]}"#,
+    );
+    let found = best(&mut synth, 3, 3);
+    assert!(!found.is_empty());
+    assert!(found.iter().all(|code| code.contains('+')), "{found:?}");
+}
+
+#[test]
+fn solutions_avoid_forbidden_components() {
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,2],"must_not_use":[{"var":"x"}]}"#,
+    );
+    assert!(best(&mut synth, 1, 3).is_empty());
+}
+
+#[test]
+fn best_stays_within_the_limits() {
+    let task = r#"{"examples":2,"variables":{"x":[1,2]},"output":[2,null]}"#;
+    let mut fresh = synthesizer(task);
+    let mut expected = best(&mut fresh, 100, 2);
+    expected.sort();
+
+    // A bank reused after a deeper search, like the daemon's.
+    let mut reused = synthesizer(task);
+    let deeper = best(&mut reused, 100, 3);
+    let mut found = best(&mut reused, 100, 2);
+    found.sort();
+    assert!(deeper.len() > found.len());
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn ranks_the_whole_level_before_picking_the_best() {
+    // `x + 0` comes up before `x++` at level 2, but is bigger.
+    let mut synth = synthesizer(r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,2]}"#);
+    assert_eq!(best(&mut synth, 2, 3), ["x", "x++"]);
+}
+
+fn with_predicate<T>(json: &str, pred: impl Fn(&[T], &AfterState) -> bool + 'static) -> Synthesizer
+where
+    AnyPredicate: From<Predicate<T>>,
+{
+    let task = loader::parse_task(json)
+        .expect("valid task")
+        .with_predicate(pred);
+    Synthesizer::new(Vocab::all(), task)
+}
+
+fn is_solution(synth: &mut Synthesizer, code: &str) -> bool {
+    let snippet = synth.parse(code).expect("valid code");
+    synth.get(snippet).is_solution()
+}
+
+#[test]
+fn solutions_satisfy_int_predicates() {
+    // Twice whatever `x` is, without saying what that is.
+    let mut synth = with_predicate(
+        r#"{"examples":2,"variables":{"x":[1,2]},"after":{"x":[1,2]}}"#,
+        |values: &[Int], after| {
+            let Anies::Int(x) = &after["x"] else {
+                return false;
+            };
+            values.iter().zip(x).all(|(value, x)| *value == 2 * x)
+        },
+    );
+    assert_eq!(best(&mut synth, 1, 3), ["x + x"]);
+    assert!(is_solution(&mut synth, "x + x"));
+    assert!(!is_solution(&mut synth, "x + 1"));
+}
+
+#[test]
+fn solutions_satisfy_str_predicates() {
+    // The last character of `s`.
+    let mut synth = with_predicate(
+        r#"{"examples":2,"variables":{"s":["ab","cde"]}}"#,
+        |values: &[Str], after| {
+            let Anies::Str(s) = &after["s"] else {
+                return false;
+            };
+            values
+                .iter()
+                .zip(s)
+                .all(|(value, s)| s.ends_with(value.as_str()) && value.len() == 1)
+        },
+    );
+    assert_eq!(best(&mut synth, 1, 4), ["s[s.length + -1]"]);
+    assert!(is_solution(&mut synth, "s[s.length - 1]"));
+    assert!(!is_solution(&mut synth, "s[1]"));
+}
+
+#[test]
+fn ranks_by_the_ranking_given() {
+    let task = r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,2]}"#;
+    // Anything that doesn't change `x` first.
+    let no_mutation = |lhs: &SnippetRef, rhs: &SnippetRef| {
+        let mutates = |snippet: &SnippetRef| snippet.code().contains("++");
+        mutates(lhs)
+            .cmp(&mutates(rhs))
+            .then_with(|| default_ranking(lhs, rhs))
+    };
+
+    let mut synth = synthesizer(task);
+    let (found, _) = synth.best_by(2, &Limits::new(3), &no_mutation);
+    let found: Vec<String> = found
+        .into_iter()
+        .map(|snippet| synth.get(snippet).code())
+        .collect();
+    assert_eq!(found, ["x", "x + 0"]);
+
+    let mut synth = synthesizer(task);
+    assert_eq!(best(&mut synth, 2, 3), ["x", "x++"]);
+}
+
+#[test]
+fn parenthesizes_code_only_where_needed() {
+    for code in [
+        "x - (y + z)",
+        "x - y + z",
+        "-(x + y)",
+        "-x + y",
+        "-(-1)",
+        "x - -y",
+        "s[x - 1].length",
+    ] {
+        // A fresh bank without constants, so it has nothing equivalent
+        // to give back instead.
+        let mut synth = synthesizer(
+            r#"{"examples":2,"variables":{"x":[1,2],"y":[10,30],"z":[100,500],"s":["ab","cde"]},
+                "output":[0,0],"constants":[]}"#,
+        );
+        let snippet = synth.parse(code).expect("valid code");
+        assert_eq!(synth.get(snippet).code(), code);
+    }
+}
+
+#[test]
+fn renders_statements_only_when_the_value_is_unused() {
+    let mut synth = synthesizer(r#"{"examples":1,"variables":{"x":[1]},"after":{"x":[2]}}"#);
+    let inc = synth.parse("x++").expect("valid code");
+    assert_eq!(synth.get(inc).render(Lang::Python).unwrap(), "x += 1");
+
+    let mut synth =
+        synthesizer(r#"{"examples":1,"variables":{"x":[1]},"output":[1],"after":{"x":[2]}}"#);
+    let inc = synth.parse("x++").expect("valid code");
+    assert!(synth.get(inc).render(Lang::Python).is_err());
+    assert!(synth.get(inc).render(Lang::Rust).is_err());
+}
+
+#[test]
+fn keeps_programs_that_add_a_required_component() {
+    // `x - 0` is equivalent to `x + 0`, which we find first,
+    // but only the former uses `-`.
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,2],"must_use":[{"op":"sub"}]}"#,
+    );
+    assert_eq!(best(&mut synth, 1, 3), ["x - 0"]);
+}
+
+/// The code of every filling of `sketch` within `max_level`, and why we stopped.
+fn fillings(synth: &mut Synthesizer, sketch: &str, max_level: u8) -> (Vec<String>, Stop) {
+    let sketch = synth.sketch(sketch).expect("valid sketch");
+    let mut fillings = synth.fill_sketch(sketch, &Limits::new(max_level));
+    let found = fillings.by_ref().map(|filling| filling.code).collect();
+    (found, fillings.stop().expect("stopped"))
+}
+
+#[test]
+fn fills_sketch_holes() {
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"s":["abc","hello"],"x":[1,3]},"output":["c","o"]}"#,
+    );
+    let (found, _) = fillings(&mut synth, "s[?? + 1]", 2);
+    assert_eq!(found.first().map(String::as_str), Some("s[x + 1]"));
+
+    let mut synth =
+        synthesizer(r#"{"examples":2,"variables":{"arr":[[1,2,3],[4,5]]},"output":[[3],[5]]}"#);
+    let (found, _) = fillings(&mut synth, "arr.slice(??)", 2);
+    assert_eq!(found.first().map(String::as_str), Some("arr.slice(-1)"));
+}
+
+#[test]
+fn stops_when_no_filling_works() {
+    let mut synth =
+        synthesizer(r#"{"examples":2,"variables":{"s":["abc","hello"]},"output":["z","z"]}"#);
+    let (found, stop) = fillings(&mut synth, "s[??]", 2);
+    assert!(found.is_empty());
+    assert_eq!(stop, Stop::Exhausted);
+
+    // Without holes there's only one program to try.
+    let (found, stop) = fillings(&mut synth, "s[0]", 10);
+    assert!(found.is_empty());
+    assert_eq!(stop, Stop::Exhausted);
+}
+
+#[test]
+fn solutions_avoid_forbidden_outputs() {
+    let mut synth = synthesizer(r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,null]}"#);
+    assert_eq!(best(&mut synth, 1, 3), ["x"]);
+
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"x":[1,2]},"output":[1,null],
+            "forbidden_outputs":[{"example":1,"value":2}]}"#,
+    );
+    assert_eq!(best(&mut synth, 1, 3), ["1"]);
+}
+
+#[test]
+fn solutions_avoid_forbidden_after_states() {
+    let mut synth =
+        synthesizer(r#"{"examples":2,"variables":{"a":[[1],[2]]},"after":{"a":[[1,1],null]}}"#);
+    assert_eq!(best(&mut synth, 1, 3), ["a.push(1)"]);
+
+    let mut synth = synthesizer(
+        r#"{"examples":2,"variables":{"a":[[1],[2]]},"after":{"a":[[1,1],null]},
+            "forbidden_after":[{"example":1,"var":"a","value":[2,1]}]}"#,
+    );
+    assert_eq!(best(&mut synth, 1, 3), ["a.push(a[0])"]);
+}
+
+#[test]
+#[should_panic(expected = "`y`, which isn't a variable of this task")]
+fn forbidden_after_states_need_a_variable() {
+    let task = loader::parse_task(r#"{"examples":1,"variables":{"x":[1]},"output":[2]}"#).unwrap();
+    task.with_forbidden_after_state(0, "y".to_string(), Any::Int(2));
+}
+
+#[test]
+fn solutions_leave_read_only_variables_alone() {
+    let task = r#"{"examples":2,"variables":{"x":[1,2],"y":[1,2]},"after":{"y":[2,3]}"#;
+    let mutates_x = |code: &str| code.contains("x++") || code.contains("x--");
+
+    // Without `read_only`, some solutions bump `x` on the way, like `x++ + y++`.
+    let mut synth = synthesizer(&format!("{task}}}"));
+    assert!(best(&mut synth, 100, 3).iter().any(|code| mutates_x(code)));
+
+    let mut synth = synthesizer(&format!(r#"{task},"read_only":["x"]}}"#));
+    let (found, _) = synth.best(100, &Limits::new(3));
+    assert!(!found.is_empty());
+    for snippet in found {
+        let snippet = synth.get(snippet);
+        assert!(!mutates_x(&snippet.code()), "{}", snippet.code());
+        assert_eq!(snippet.after_state()["x"], Anies::Int(vec![1, 2]));
+    }
+}
+
+/// The code of every program `synth` enumerates until it stops.
+fn enumerate(synth: &mut Synthesizer) -> Vec<String> {
+    let snippets: Vec<_> = synth.by_ref().collect();
+    snippets
+        .into_iter()
+        .map(|snippet| synth.get(snippet).code())
+        .collect()
+}
+
+#[test]
+fn iterating_stops_once_a_level_adds_nothing() {
+    let vocab = Vocab::select(&["inc", "minus"]).unwrap();
+    let task =
+        loader::parse_task(r#"{"examples":1,"variables":{"x":[1]},"output":[5],"constants":[]}"#);
+    let mut synth = Synthesizer::new(vocab, task.unwrap()).with_limits(Limits::new(10));
+    assert!(!enumerate(&mut synth).is_empty());
+    assert_eq!(synth.stop(), Some(Stop::Exhausted));
+    assert!(synth.level() < 10);
+    assert_eq!(synth.next(), None);
+}
+
+#[test]
+fn iterating_stops_once_the_bank_is_full() {
+    let mut synth = synthesizer(r#"{"examples":2,"variables":{"x":[1,2]},"output":[7,9]}"#)
+        .with_limits(Limits::new(10).with_max_bank_size(20));
+    enumerate(&mut synth);
+    assert_eq!(synth.stop(), Some(Stop::BankFull));
+    assert_eq!(synth.total_bank_size(), 20);
+}
+
+#[test]
+fn iterating_resumes_where_it_stopped() {
+    let task = r#"{"examples":2,"variables":{"x":[1,2]},"output":[7,9]}"#;
+    let mut expected = synthesizer(task).with_limits(Limits::new(3));
+    let expected = enumerate(&mut expected);
+
+    let mut synth = synthesizer(task).with_limits(Limits::new(2));
+    let mut found = enumerate(&mut synth);
+    assert_eq!(synth.stop(), Some(Stop::Exhausted));
+    assert!(found.len() < expected.len());
+
+    synth.set_limits(Limits::new(3));
+    assert_eq!(synth.stop(), None);
+    found.extend(enumerate(&mut synth));
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn iterating_stops_after_the_last_level() {
+    // Pushing lengths onto `a` keeps making new arrays, one level at a time.
+    let vocab = Vocab::select(&["arr_push", "arr_len"]).unwrap();
+    let task =
+        loader::parse_task(r#"{"examples":1,"variables":{"a":[[]]},"output":[5],"constants":[]}"#);
+    let mut synth = Synthesizer::new(vocab, task.unwrap()).with_limits(Limits::new(u8::MAX));
+    assert!(!enumerate(&mut synth).is_empty());
+    assert_eq!(synth.stop(), Some(Stop::Exhausted));
+    assert_eq!(synth.level(), u8::MAX);
+}